@@ -0,0 +1,144 @@
+//! Issues of submodules and the problems reported by `magoo status --check`
+
+/// An issue of a submodule that makes it unhealthy
+///
+/// The issues can be fixed with [`Submodule::fix`](crate::submodule::Submodule::fix)
+/// (`magoo status --fix`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum Issue {
+    /// The data in `.git/modules/<name>` is not consistent with the worktree,
+    /// see [`InGitModule::is_consistent`](crate::submodule::InGitModule::is_consistent)
+    InconsistentModule,
+    /// The paths stored in different places are not the same,
+    /// see [`SubmodulePaths::is_consistent`](crate::submodule::SubmodulePaths::is_consistent)
+    InconsistentPaths,
+    /// There are remains of the submodule in `.git/config` or `.git/modules` after it's
+    /// deinitialized
+    Residue,
+    /// The submodule is in `.gitmodules` but not in the index
+    MissingIndex,
+    /// The submodule is in the index but not in `.gitmodules`
+    #[cfg_attr(feature = "serde", serde(rename = "missing_in_gitmodules"))]
+    MissingInGitModules,
+    /// The submodule is not in `.gitmodules` or the index, but has remains in other places
+    #[cfg_attr(feature = "serde", serde(rename = "missing_index_and_gitmodules"))]
+    MissingIndexAndGitModules,
+}
+
+impl Issue {
+    /// Get a human-readable description of the issue
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Issue::InconsistentModule => "submodule has residue",
+            Issue::InconsistentPaths => "inconsistent paths",
+            Issue::Residue => "inconsistent state (has residue from removal)",
+            Issue::MissingIndex => "inconsistent state (missing in index)",
+            Issue::MissingInGitModules => "inconsistent state (not in .gitmodules)",
+            Issue::MissingIndexAndGitModules => {
+                "inconsistent state (missing in index and .gitmodules)"
+            }
+        }
+    }
+
+    /// Get an explanation of how the issue usually happens, and what `magoo status --fix` does
+    /// to fix it
+    pub fn explain(&self) -> &'static str {
+        match self {
+            Issue::InconsistentModule => {
+                "The data in `.git/modules/<name>` is incomplete, or its git directory is not \
+                 `.git/modules/<name>`, usually because it was copied or edited by hand. Fixing \
+                 removes `.git/modules/<name>`, and the submodule can be cloned again with \
+                 `magoo install`."
+            }
+            Issue::InconsistentPaths => {
+                "The path of the submodule in .gitmodules, the index and .git/modules are not the \
+                 same, usually because the submodule was moved without `git mv`. Fixing updates \
+                 .gitmodules to the path in the index, and removes `.git/modules/<name>` if it \
+                 has a different path."
+            }
+            Issue::Residue => {
+                "The submodule was removed or deinitialized, but `.git/config` or \
+                 `.git/modules/<name>` still have its data. Fixing removes the leftover data."
+            }
+            Issue::MissingIndex => {
+                "The submodule is in .gitmodules but not in the index, usually because the index \
+                 entry was removed with `git rm --cached`. Fixing adds the submodule again from \
+                 .gitmodules, or deletes it with `--delete`."
+            }
+            Issue::MissingInGitModules => {
+                "The submodule is in the index but not in .gitmodules, usually because .gitmodules \
+                 was edited by hand. Fixing deletes the submodule."
+            }
+            Issue::MissingIndexAndGitModules => {
+                "The submodule is not in .gitmodules or the index, but `.git/config` or \
+                 `.git/modules/<name>` still have its data. Fixing deletes the leftover data."
+            }
+        }
+    }
+}
+
+/// A problem found by `magoo status --check`
+///
+/// Each problem class has a distinct exit code. The variants are ordered by severity, with the
+/// most severe one first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckFailure {
+    /// The submodule is not healthy (exit code 10-15, depending on the issue)
+    Unhealthy(Issue),
+    /// The submodule is not initialized (exit code 16)
+    NotInitialized,
+    /// The commit checked out is different from the one in the index (exit code 17)
+    CheckedOutDiffers,
+}
+
+impl CheckFailure {
+    /// Get the exit code of the process when this is the most severe problem found
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CheckFailure::Unhealthy(issue) => match issue {
+                Issue::InconsistentModule => 10,
+                Issue::InconsistentPaths => 11,
+                Issue::Residue => 12,
+                Issue::MissingIndex => 13,
+                Issue::MissingInGitModules => 14,
+                Issue::MissingIndexAndGitModules => 15,
+            },
+            CheckFailure::NotInitialized => 16,
+            CheckFailure::CheckedOutDiffers => 17,
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            CheckFailure::Unhealthy(issue) => issue.describe(),
+            CheckFailure::NotInitialized => "not initialized",
+            CheckFailure::CheckedOutDiffers => "checked out commit is different from index",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_failure_exit_codes() {
+        let failures = [
+            CheckFailure::Unhealthy(Issue::InconsistentModule),
+            CheckFailure::Unhealthy(Issue::InconsistentPaths),
+            CheckFailure::Unhealthy(Issue::Residue),
+            CheckFailure::Unhealthy(Issue::MissingIndex),
+            CheckFailure::Unhealthy(Issue::MissingInGitModules),
+            CheckFailure::Unhealthy(Issue::MissingIndexAndGitModules),
+            CheckFailure::NotInitialized,
+            CheckFailure::CheckedOutDiffers,
+        ];
+        for pair in failures.windows(2) {
+            assert!(pair[0] < pair[1]);
+            assert!(pair[0].exit_code() < pair[1].exit_code());
+        }
+    }
+}
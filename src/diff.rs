@@ -0,0 +1,178 @@
+//! Comparing submodules between revisions of the superproject
+
+use crate::status::Status;
+use crate::submodule::Submodule;
+
+/// Change of one submodule between two revisions
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubmoduleDiff {
+    /// Name of the submodule, or the path if the submodule doesn't have a name
+    pub name: String,
+    /// The submodule in the old revision. [`None`] if the submodule is added
+    pub old: Option<Submodule>,
+    /// The submodule in the new revision. [`None`] if the submodule is removed
+    pub new: Option<Submodule>,
+}
+
+impl SubmoduleDiff {
+    /// Compare the submodules in two [`Status`], usually read with
+    /// [`Status::read_from_revision`]. Only the submodules that changed are returned.
+    ///
+    /// Submodules are matched by name, or by path if they don't have a name.
+    pub fn compute(old: &Status, new: &Status) -> Vec<Self> {
+        let mut diffs = Vec::new();
+        let new_modules = new.flattened();
+        let mut matched = vec![false; new_modules.len()];
+        for old_module in old.flattened() {
+            let name = match key(old_module) {
+                Some(name) => name,
+                None => continue,
+            };
+            let new_index = new_modules
+                .iter()
+                .position(|new_module| key(new_module) == Some(name));
+            let new_module = match new_index {
+                Some(i) => {
+                    matched[i] = true;
+                    Some(new_modules[i].clone())
+                }
+                None => None,
+            };
+            diffs.push(Self {
+                name: name.to_string(),
+                old: Some(old_module.clone()),
+                new: new_module,
+            });
+        }
+        for (new_module, matched) in new_modules.into_iter().zip(matched) {
+            if matched {
+                continue;
+            }
+            if let Some(name) = key(new_module) {
+                diffs.push(Self {
+                    name: name.to_string(),
+                    old: None,
+                    new: Some(new_module.clone()),
+                });
+            }
+        }
+
+        diffs.retain(|diff| diff.has_changes());
+        diffs.sort_by(|a, b| a.name.cmp(&b.name));
+        diffs
+    }
+
+    /// Return true if the submodule only exists in the new revision
+    pub fn is_added(&self) -> bool {
+        self.old.is_none() && self.new.is_some()
+    }
+
+    /// Return true if the submodule only exists in the old revision
+    pub fn is_removed(&self) -> bool {
+        self.old.is_some() && self.new.is_none()
+    }
+
+    /// Get the old and new path if the submodule is moved
+    pub fn moved(&self) -> Option<(&str, &str)> {
+        let old = self.old.as_ref()?.path()?;
+        let new = self.new.as_ref()?.path()?;
+        (old != new).then_some((old, new))
+    }
+
+    /// Get the old and new commit if the submodule points to a different commit
+    pub fn repointed(&self) -> Option<(&str, &str)> {
+        let old = self.old.as_ref()?.index_commit()?;
+        let new = self.new.as_ref()?.index_commit()?;
+        (old != new).then_some((old, new))
+    }
+
+    /// Get the old and new URL if the URL is changed
+    pub fn url_changed(&self) -> Option<(Option<&str>, Option<&str>)> {
+        let old = self.old.as_ref()?.url();
+        let new = self.new.as_ref()?.url();
+        (old != new).then_some((old, new))
+    }
+
+    /// Get the old and new update branch if the branch is changed
+    pub fn branch_changed(&self) -> Option<(Option<&str>, Option<&str>)> {
+        let old = self.old.as_ref()?.branch();
+        let new = self.new.as_ref()?.branch();
+        (old != new).then_some((old, new))
+    }
+
+    /// Return true if there is any change
+    pub fn has_changes(&self) -> bool {
+        self.is_added()
+            || self.is_removed()
+            || self.moved().is_some()
+            || self.repointed().is_some()
+            || self.url_changed().is_some()
+            || self.branch_changed().is_some()
+    }
+}
+
+/// Key to match the submodules in different revisions
+fn key(submodule: &Submodule) -> Option<&str> {
+    submodule.name().or(submodule.path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::submodule::{InGitmodules, IndexObject};
+
+    fn submodule(name: &str, path: &str, url: &str, sha: &str) -> Submodule {
+        Submodule {
+            in_gitmodules: Some(InGitmodules {
+                name: name.to_string(),
+                path: Some(path.to_string()),
+                url: Some(url.to_string()),
+                ..Default::default()
+            }),
+            in_index: Some(IndexObject {
+                path: path.to_string(),
+                sha: sha.to_string(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn status(submodules: Vec<Submodule>) -> Status {
+        let mut status = Status::default();
+        for submodule in submodules {
+            let name = submodule.name().unwrap().to_string();
+            status.modules.insert(name, submodule);
+        }
+        status
+    }
+
+    #[test]
+    fn test_compute() {
+        let old = status(vec![
+            submodule("same", "same", "url", "1111111"),
+            submodule("removed", "removed", "url", "1111111"),
+            submodule("moved", "old/path", "url", "1111111"),
+            submodule("bumped", "bumped", "url", "1111111"),
+            submodule("url", "url", "old-url", "1111111"),
+        ]);
+        let new = status(vec![
+            submodule("same", "same", "url", "1111111"),
+            submodule("added", "added", "url", "2222222"),
+            submodule("moved", "new/path", "url", "1111111"),
+            submodule("bumped", "bumped", "url", "2222222"),
+            submodule("url", "url", "new-url", "1111111"),
+        ]);
+        let diffs = SubmoduleDiff::compute(&old, &new);
+        let names = diffs.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["added", "bumped", "moved", "removed", "url"]);
+        assert!(diffs[0].is_added());
+        assert_eq!(diffs[1].repointed(), Some(("1111111", "2222222")));
+        assert_eq!(diffs[1].moved(), None);
+        assert_eq!(diffs[2].moved(), Some(("old/path", "new/path")));
+        assert!(diffs[3].is_removed());
+        assert_eq!(
+            diffs[4].url_changed(),
+            Some((Some("old-url"), Some("new-url")))
+        );
+    }
+}
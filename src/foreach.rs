@@ -0,0 +1,118 @@
+//! Running a command in the worktrees of multiple submodules in parallel
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A command to run in the worktree of one submodule
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeachJob {
+    /// Name to display in the output, which includes the parent for nested submodules
+    pub display_name: String,
+    /// Name of the submodule, exposed as `$name`
+    pub name: String,
+    /// Path of the submodule from the top level of its superproject, exposed as `$sm_path` (and
+    /// `$path` except on Windows, where it would replace `PATH`)
+    pub path: String,
+    /// The commit checked out in the submodule, exposed as `$sha`
+    pub sha: String,
+    /// Absolute path of the top level of its superproject, exposed as `$toplevel`
+    pub toplevel: String,
+    /// The worktree of the submodule to run the command in
+    pub dir: PathBuf,
+}
+
+impl ForeachJob {
+    /// Run the command with the shell in the worktree and wait for the output
+    pub fn run(&self, command: &str) -> std::io::Result<Output> {
+        let mut cmd = shell_command(command);
+        cmd.current_dir(&self.dir)
+            .env("name", &self.name)
+            .env("sm_path", &self.path)
+            .env("sha", &self.sha)
+            .env("toplevel", &self.toplevel);
+        #[cfg(not(windows))]
+        cmd.env("path", &self.path);
+        cmd.output()
+    }
+}
+
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+/// Run the command for every job, with at most `parallel` jobs at the same time.
+///
+/// `on_done` is called when each job finishes, one at a time, so the output of different jobs
+/// is not interleaved. Returns if each job succeeded, in the same order as the jobs
+pub fn run_parallel<F>(jobs: &[ForeachJob], command: &str, parallel: usize, on_done: F) -> Vec<bool>
+where
+    F: Fn(&ForeachJob, &std::io::Result<Output>) + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![false; jobs.len()]);
+    let workers = parallel.clamp(1, jobs.len().max(1));
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let job = match jobs.get(i) {
+                        Some(job) => job,
+                        None => break,
+                    };
+                    let output = job.run(command);
+                    let success = matches!(&output, Ok(output) if output.status.success());
+                    // holding the lock while reporting keeps the output of each job together
+                    let mut results = results.lock().unwrap();
+                    on_done(job, &output);
+                    results[i] = success;
+                }
+            });
+        }
+    });
+    results.into_inner().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(name: &str) -> ForeachJob {
+        ForeachJob {
+            display_name: name.to_string(),
+            name: name.to_string(),
+            path: name.to_string(),
+            sha: String::new(),
+            toplevel: String::new(),
+            dir: PathBuf::from("."),
+        }
+    }
+
+    #[test]
+    fn test_run_parallel() {
+        let jobs = vec![job("a"), job("b"), job("c")];
+        let done = AtomicUsize::new(0);
+        let results = run_parallel(&jobs, "exit 0", 2, |_, _| {
+            done.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(results, vec![true, true, true]);
+        assert_eq!(done.load(Ordering::SeqCst), 3);
+
+        let results = run_parallel(&jobs, "exit 1", 4, |_, _| {});
+        assert_eq!(results, vec![false, false, false]);
+
+        assert!(run_parallel(&[], "exit 0", 0, |_, _| {}).is_empty());
+    }
+}
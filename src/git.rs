@@ -8,7 +8,9 @@ use std::process::{Command, ExitStatus, Stdio};
 use std::time::Duration;
 
 use fs4::fs_std::FileExt;
+use semver::Version;
 
+use crate::check::CheckFailure;
 use crate::print::{
     self, println_error, println_hint, println_info, println_verbose, println_warn,
 };
@@ -58,10 +60,7 @@ impl GitContext {
     /// Check if the version is supported. If print is true, it will print the info when the
     /// version is supported. Otherwise only print if it's not supported
     pub fn check_version(&self, print: bool) -> Result<(), GitError> {
-        let out = self.run_git_command(&["--version"], false)?.join("");
-        let version = version::parse_git_version(&out).ok_or_else(|| {
-            GitError::UnsupportedVersion("nnable to parse git version".to_string())
-        })?;
+        let version = self.git_version()?;
         if !version::is_supported(&version) {
             println_error!("Magoo does not support your git version!");
             println_error!("Your version is: {}", version);
@@ -85,6 +84,13 @@ impl GitContext {
         Ok(())
     }
 
+    /// Run `git --version` and parse the version
+    pub fn git_version(&self) -> Result<Version, GitError> {
+        let out = self.run_git_command(&["--version"], false)?.join("");
+        version::parse_git_version(&out)
+            .ok_or_else(|| GitError::UnsupportedVersion("nnable to parse git version".to_string()))
+    }
+
     /// Get the absolute path to the .git directory
     pub fn git_dir(&self) -> Result<&PathBuf, GitError> {
         if let Some(git_dir) = self.git_dir_cell.get() {
@@ -195,12 +201,12 @@ impl GitContext {
             }
         }
 
-        if print::is_verbose() {
-            if let Some(stderr) = child.stderr.take() {
-                let reader = BufReader::new(stderr);
-                for line in reader.lines().map_while(Result::ok) {
-                    println_verbose!("{line}");
-                }
+        if print::is_verbose()
+            && let Some(stderr) = child.stderr.take()
+        {
+            let reader = BufReader::new(stderr);
+            for line in reader.lines().map_while(Result::ok) {
+                println_verbose!("{line}");
             }
         }
         let status = child.wait().map_err(|e| {
@@ -227,6 +233,29 @@ impl GitContext {
         Ok(())
     }
 
+    /// Run `git status --porcelain` and return the output lines
+    pub fn status_porcelain(&self) -> Result<Vec<String>, GitError> {
+        self.run_git_command(&["status", "--porcelain"], false)
+    }
+
+    /// Run `git status --porcelain --ignored -- <path>`. The path should be relative from repo
+    /// top level
+    pub fn status_porcelain_ignored(&self, path: &str) -> Result<Vec<String>, GitError> {
+        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
+        self.run_git_command(
+            &[
+                "-C",
+                &top_level_dir,
+                "status",
+                "--porcelain",
+                "--ignored",
+                "--",
+                path,
+            ],
+            false,
+        )
+    }
+
     /// Run `git -C top_level ls-files ...`
     pub fn ls_files(&self, extra_args: &[&str]) -> Result<Vec<String>, GitError> {
         let top_level_dir = self.top_level_dir()?.to_cmd_arg();
@@ -235,6 +264,15 @@ impl GitContext {
         self.run_git_command(&args, false)
     }
 
+    /// Run `git -C top_level ls-tree -r --full-tree ... <rev>`
+    pub fn ls_tree(&self, rev: &str, extra_args: &[&str]) -> Result<Vec<String>, GitError> {
+        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
+        let mut args = vec!["-C", &top_level_dir, "ls-tree", "-r", "--full-tree"];
+        args.extend_from_slice(extra_args);
+        args.push(rev);
+        self.run_git_command(&args, false)
+    }
+
     /// Run `git describe --all <commit>` and return the first output
     pub fn describe(&self, commit: &str) -> Option<String> {
         self.run_git_command(&["describe", "--all", commit], false)
@@ -242,12 +280,214 @@ impl GitContext {
             .and_then(|x| x.into_iter().next())
     }
 
+    /// Run `git describe --tags <commit>` and return the first output
+    pub fn describe_tags(&self, commit: &str) -> Option<String> {
+        self.run_git_command(&["describe", "--tags", commit], false)
+            .ok()
+            .and_then(|x| x.into_iter().next())
+    }
+
     /// Run `git rev-parse HEAD`
     pub fn head(&self) -> Result<Option<String>, GitError> {
         let output = self.run_git_command(&["rev-parse", "HEAD"], false)?;
         Ok(output.into_iter().next())
     }
 
+    /// Run `git rev-parse --verify --quiet <rev>^{commit}` and return the commit, or [`None`] if
+    /// the revision cannot be resolved to a commit
+    pub fn rev_parse(&self, rev: &str) -> Option<String> {
+        let rev = format!("{rev}^{{commit}}");
+        self.run_git_command(&["rev-parse", "--verify", "--quiet", &rev], false)
+            .ok()
+            .and_then(|x| x.into_iter().next())
+    }
+
+    /// Run `git symbolic-ref --short HEAD` to get the current branch, or [`None`] if HEAD is
+    /// detached
+    pub fn current_branch(&self) -> Option<String> {
+        self.run_git_command(&["symbolic-ref", "--short", "--quiet", "HEAD"], false)
+            .ok()
+            .and_then(|x| x.into_iter().next())
+    }
+
+    /// Run `git config --get remote.<remote>.url` and return the URL, or [`None`] if the remote
+    /// doesn't exist
+    pub fn remote_url(&self, remote: &str) -> Option<String> {
+        self.run_git_command(&["config", "--get", &format!("remote.{remote}.url")], false)
+            .ok()
+            .and_then(|x| x.into_iter().next())
+    }
+
+    /// Run `git config --get <key>` to get the value from all config files (system, global and
+    /// the repository), or [`None`] if the key is not set
+    pub fn get_effective_config(&self, key: &str) -> Option<String> {
+        self.run_git_command(&["config", "--get", key], false)
+            .ok()
+            .and_then(|x| x.into_iter().next())
+    }
+
+    /// Get the default remote, which is the remote of the current branch, or `origin`
+    ///
+    /// This is the same remote `git submodule update --remote` uses.
+    pub fn default_remote(&self) -> Result<String, GitError> {
+        if let Some(branch) = self.current_branch() {
+            let config_path = self.git_dir()?.join("config");
+            if let Ok(Some(remote)) =
+                self.get_config(config_path, &format!("branch.{branch}.remote"))
+            {
+                return Ok(remote);
+            }
+        }
+        Ok("origin".to_string())
+    }
+
+    /// Run `git rev-list --left-right --count <left>...<right>` and return the number of commits
+    /// only reachable from left and the number of commits only reachable from right
+    pub fn count_left_right(&self, left: &str, right: &str) -> Result<(usize, usize), GitError> {
+        let range = format!("{left}...{right}");
+        let output = self
+            .run_git_command(&["rev-list", "--left-right", "--count", &range], false)?
+            .join("");
+        let mut parts = output.split_whitespace().map(|x| x.parse::<usize>());
+        match (parts.next(), parts.next()) {
+            (Some(Ok(left)), Some(Ok(right))) => Ok((left, right)),
+            _ => Err(GitError::UnexpectedOutput(format!(
+                "cannot parse commit counts: {output}"
+            ))),
+        }
+    }
+
+    /// Run `git log --format=%h %s <range>` and return the commits, one per line
+    pub fn log_oneline(&self, range: &str) -> Result<Vec<String>, GitError> {
+        self.run_git_command(&["log", "--format=%h %s", range], false)
+    }
+
+    /// Run `git checkout --detach <commit>`
+    pub fn checkout_detach(&self, commit: &str) -> Result<(), GitError> {
+        self.run_git_command(&["checkout", "--quiet", "--detach", commit], false)?;
+        Ok(())
+    }
+
+    /// Run `git diff --cached --name-only` and return if there are staged changes
+    pub fn has_staged_changes(&self) -> Result<bool, GitError> {
+        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
+        let output = self.run_git_command(
+            &["-C", &top_level_dir, "diff", "--cached", "--name-only"],
+            false,
+        )?;
+        Ok(!output.is_empty())
+    }
+
+    /// Run `git subtree split --prefix=<path> HEAD` and return the split commit. The path should
+    /// be relative from repo top level
+    pub fn subtree_split(&self, path: &str) -> Result<String, GitError> {
+        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
+        let prefix = format!("--prefix={path}");
+        let output = self.run_git_command(
+            &[
+                "-C",
+                &top_level_dir,
+                "subtree",
+                "split",
+                "-q",
+                &prefix,
+                "HEAD",
+            ],
+            false,
+        )?;
+        output.into_iter().last().ok_or_else(|| {
+            GitError::UnexpectedOutput("git subtree split did not return a commit".to_string())
+        })
+    }
+
+    /// Run `git init --bare <path>`
+    pub fn init_bare(&self, path: &str) -> Result<(), GitError> {
+        self.run_git_command(&["init", "--bare", "--quiet", path], false)?;
+        Ok(())
+    }
+
+    /// Run `git push <remote> <refspec>`
+    pub fn push(&self, remote: &str, refspec: &str) -> Result<(), GitError> {
+        self.run_git_command(&["push", "--quiet", remote, refspec], false)?;
+        Ok(())
+    }
+
+    /// Run `git rm -r` to remove a directory from the index and the worktree. The path should be
+    /// relative from repo top level
+    pub fn remove_recursive(&self, path: &str) -> Result<(), GitError> {
+        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
+        self.run_git_command(
+            &["-C", &top_level_dir, "rm", "-r", "--quiet", "--", path],
+            false,
+        )?;
+        Ok(())
+    }
+
+    /// Run `git restore --staged --worktree --source=HEAD` to restore a path to `HEAD`. The path
+    /// should be relative from repo top level
+    pub fn restore_from_head(&self, path: &str) -> Result<(), GitError> {
+        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
+        self.run_git_command(
+            &[
+                "-C",
+                &top_level_dir,
+                "restore",
+                "--staged",
+                "--worktree",
+                "--source=HEAD",
+                "--",
+                path,
+            ],
+            false,
+        )?;
+        Ok(())
+    }
+
+    /// Run `git commit -m <message>` to commit the staged changes
+    pub fn commit(&self, message: &str) -> Result<(), GitError> {
+        self.run_git_command(&["commit", "--quiet", "-m", message], false)?;
+        Ok(())
+    }
+
+    /// Run `git commit -m <message> -- <paths>` to commit only the paths, leaving other staged
+    /// changes in the index. Paths should be from top level
+    pub fn commit_paths(&self, message: &str, paths: &[&str]) -> Result<(), GitError> {
+        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
+        let mut args = vec![
+            "-C",
+            &top_level_dir,
+            "commit",
+            "--quiet",
+            "-m",
+            message,
+            "--",
+        ];
+        args.extend_from_slice(paths);
+        self.run_git_command(&args, false)?;
+        Ok(())
+    }
+
+    /// Run `git fetch <remote> [<refspec>]`
+    pub fn fetch(&self, remote: &str, refspec: Option<&str>) -> Result<(), GitError> {
+        let mut args = vec!["fetch", remote];
+        if let Some(refspec) = refspec {
+            args.push(refspec);
+        }
+        self.run_git_command(&args, false)?;
+        Ok(())
+    }
+
+    /// Run `git fetch --tags <remote>`
+    pub fn fetch_tags(&self, remote: &str) -> Result<(), GitError> {
+        self.run_git_command(&["fetch", "--tags", remote], false)?;
+        Ok(())
+    }
+
+    /// Run `git tag --list`
+    pub fn list_tags(&self) -> Result<Vec<String>, GitError> {
+        self.run_git_command(&["tag", "--list"], false)
+    }
+
     /// Run `git config -f config_path --get key`
     ///
     /// The config path is resolved relative to the working directory of this context.
@@ -275,21 +515,33 @@ impl GitContext {
         S: AsRef<Path>,
     {
         let config_path = config_path.to_cmd_arg();
-        let name_and_values = self.run_git_command(
-            &["config", "-f", &config_path, "--get-regexp", regexp],
-            false,
-        )?;
-        let name_only = self.run_git_command(
-            &[
-                "config",
-                "-f",
-                &config_path,
-                "--name-only",
-                "--get-regexp",
-                regexp,
-            ],
-            false,
-        )?;
+        self.get_config_regexp_from(&["-f", &config_path], regexp)
+    }
+
+    /// Calls `git config --blob blob ... --get-regexp regexp` to get (key, value) pairs in a
+    /// config file stored as a blob, like `HEAD:.gitmodules`
+    pub fn get_blob_config_regexp(
+        &self,
+        blob: &str,
+        regexp: &str,
+    ) -> Result<Vec<(String, String)>, GitError> {
+        self.get_config_regexp_from(&["--blob", blob], regexp)
+    }
+
+    fn get_config_regexp_from(
+        &self,
+        source: &[&str],
+        regexp: &str,
+    ) -> Result<Vec<(String, String)>, GitError> {
+        let mut args = vec!["config"];
+        args.extend_from_slice(source);
+        args.extend_from_slice(&["--get-regexp", regexp]);
+        let name_and_values = self.run_git_command(&args, false)?;
+
+        let mut args = vec!["config"];
+        args.extend_from_slice(source);
+        args.extend_from_slice(&["--name-only", "--get-regexp", regexp]);
+        let name_only = self.run_git_command(&args, false)?;
 
         let mut name_values = Vec::new();
         for (name, name_and_value) in name_only.iter().zip(name_and_values.iter()) {
@@ -320,32 +572,83 @@ impl GitContext {
     where
         S: AsRef<Path>,
     {
-        let config_path = config_path.to_cmd_arg();
-        let mut args = vec!["config", "-f", &config_path];
-        match value {
-            Some(v) => {
-                args.push(key);
-                args.push(v);
-            }
-            None => {
-                args.push("--unset");
-                args.push(key);
-            }
-        }
-        self.run_git_command(&args, false)?;
-        Ok(())
+        self.run_args(&config_set_args(config_path, key, value), false)
     }
 
     /// Remove a config section from a config file.
     ///
     /// The config path is resolved relative to the working directory of this context.
     pub fn remove_config_section<S>(&self, config_path: S, section: &str) -> Result<(), GitError>
+    where
+        S: AsRef<Path>,
+    {
+        self.run_args(&config_remove_section_args(config_path, section), false)
+    }
+
+    /// Run `git rm --cached --force` to remove a path from the index, keeping the worktree. The
+    /// path should be relative from repo top level
+    ///
+    /// `--force` is needed if the staged content is different from both the worktree and `HEAD`,
+    /// for example a gitlink just added with `git add`. The worktree is not touched either way
+    pub fn remove_cached(&self, path: &str) -> Result<(), GitError> {
+        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
+        self.run_git_command(
+            &[
+                "-C",
+                &top_level_dir,
+                "rm",
+                "--cached",
+                "--force",
+                "--quiet",
+                "--",
+                path,
+            ],
+            false,
+        )?;
+        Ok(())
+    }
+
+    /// Add a gitlink (submodule commit) to the index without reading the worktree. The path
+    /// should be relative from repo top level
+    pub fn add_gitlink(&self, path: &str, commit: &str) -> Result<(), GitError> {
+        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
+        let cache_info = format!("160000,{commit},{path}");
+        self.run_git_command(
+            &[
+                "-C",
+                &top_level_dir,
+                "update-index",
+                "--add",
+                "--cacheinfo",
+                &cache_info,
+            ],
+            false,
+        )?;
+        Ok(())
+    }
+
+    /// Rename a config section in a config file.
+    ///
+    /// The config path is resolved relative to the working directory of this context.
+    pub fn rename_config_section<S>(
+        &self,
+        config_path: S,
+        old_section: &str,
+        new_section: &str,
+    ) -> Result<(), GitError>
     where
         S: AsRef<Path>,
     {
         let config_path = config_path.to_cmd_arg();
         self.run_git_command(
-            &["config", "-f", &config_path, "--remove-section", section],
+            &[
+                "config",
+                "-f",
+                &config_path,
+                "--rename-section",
+                old_section,
+                new_section,
+            ],
             false,
         )?;
         Ok(())
@@ -353,73 +656,31 @@ impl GitContext {
 
     /// Remove an object from the index and stage the change. The path should be relative from repo top level
     pub fn remove_from_index(&self, path: &str) -> Result<(), GitError> {
-        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
-
         // ignore the error because the file might not be in the index
-        let _ = self.run_git_command(&["-C", &top_level_dir, "rm", path], false);
+        let _ = self.run_top_level_args(&rm_args(path), false);
 
-        let _ = self.run_git_command(&["-C", &top_level_dir, "add", path], false);
+        let _ = self.run_top_level_args(&add_args(path), false);
         Ok(())
     }
 
     /// Run `git add`
     pub fn add(&self, path: &str) -> Result<(), GitError> {
-        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
-
-        self.run_git_command(&["-C", &top_level_dir, "add", path], false)?;
-        Ok(())
+        self.run_top_level_args(&add_args(path), false)
     }
 
     /// Runs `git submodule deinit [-- <path>]`. Path should be from top level
     pub fn submodule_deinit(&self, path: Option<&str>, force: bool) -> Result<(), GitError> {
-        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
-        let mut args = vec!["-C", &top_level_dir, "submodule", "deinit"];
-
-        if force {
-            args.push("--force");
-        }
-
-        if let Some(path) = path {
-            args.push("--");
-            args.push(path);
-        } else {
-            args.push("--all");
-        }
-        self.run_git_command(&args, true)?;
-
-        Ok(())
+        self.run_top_level_args(&submodule_deinit_args(path, force), true)
     }
 
     /// Runs `git submodule init [-- <path>]`. Path should be from top level
     pub fn submodule_init(&self, path: Option<&str>) -> Result<(), GitError> {
-        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
-        let mut args = vec!["-C", &top_level_dir, "submodule", "init"];
-
-        if let Some(path) = path {
-            args.push("--");
-            args.push(path);
-        }
-        self.run_git_command(&args, true)?;
-
-        Ok(())
+        self.run_top_level_args(&submodule_init_args(path), true)
     }
 
     /// Runs `git submodule sync [-- <path>]`. Path should be from top level
     pub fn submodule_sync(&self, path: Option<&str>, recursive: bool) -> Result<(), GitError> {
-        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
-        let mut args = vec!["-C", &top_level_dir, "submodule", "sync"];
-
-        if recursive {
-            args.push("--recursive");
-        }
-
-        if let Some(path) = path {
-            args.push("--");
-            args.push(path);
-        }
-        self.run_git_command(&args, true)?;
-
-        Ok(())
+        self.run_top_level_args(&submodule_sync_args(path, recursive), true)
     }
 
     /// Runs `git submodule set-branch`. Path should be from top level
@@ -463,37 +724,23 @@ impl GitContext {
         Ok(())
     }
 
-    /// Runs `git submodule update [-- <path>]`. Path should be from top level
+    /// Runs `git submodule update [-- <paths>...]`. Paths should be from top level. All
+    /// submodules are updated if `paths` is empty
     pub fn submodule_update(
         &self,
-        path: Option<&str>,
+        paths: &[&str],
         force: bool,
         remote: bool,
         recursive: bool,
+        jobs: Option<usize>,
     ) -> Result<(), GitError> {
-        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
-        let mut args = vec!["-C", &top_level_dir, "submodule", "update"];
-
-        if force {
-            args.push("--force");
-        }
-
-        if remote {
-            args.push("--remote");
-        }
-
-        if recursive {
-            args.push("--init");
-            args.push("--recursive");
-        }
-
-        if let Some(path) = path {
-            args.push("--");
-            args.push(path);
-        }
-        self.run_git_command(&args, true)?;
+        let args = submodule_update_args(paths, force, remote, recursive, jobs);
+        self.run_top_level_args(&args, true)
+    }
 
-        Ok(())
+    /// Runs `git submodule absorbgitdirs [-- <path>]`. Path should be from top level
+    pub fn submodule_absorbgitdirs(&self, path: Option<&str>) -> Result<(), GitError> {
+        self.run_top_level_args(&submodule_absorbgitdirs_args(path), true)
     }
 
     /// Runs `git submodule add`. Path should be from top level
@@ -506,32 +753,189 @@ impl GitContext {
         depth: Option<usize>,
         force: bool,
     ) -> Result<(), GitError> {
-        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
-        let mut args = vec!["-C", &top_level_dir, "submodule", "add"];
-        if force {
-            args.push("--force");
-        }
-        if let Some(branch) = branch {
-            args.push("--branch");
-            args.push(branch);
-        }
-        if let Some(name) = name {
-            args.push("--name");
-            args.push(name);
-        }
-        let depth = depth.map(|x| x.to_string());
-        if let Some(depth) = &depth {
-            args.push("--depth");
-            args.push(depth);
-        }
-        args.push("--");
-        args.push(url);
-        if let Some(path) = path {
-            args.push(path);
-        }
-        self.run_git_command(&args, true)?;
+        let args = submodule_add_args(url, path, branch, name, depth, force);
+        self.run_top_level_args(&args, true)
+    }
+
+    /// Runs `git submodule add` with `protocol.file.allow` set to `always`, so the submodule
+    /// can be cloned from a local path. Path should be from top level
+    pub fn submodule_add_local(
+        &self,
+        url: &str,
+        path: Option<&str>,
+        branch: Option<&str>,
+        name: Option<&str>,
+        force: bool,
+    ) -> Result<(), GitError> {
+        let args = allow_file_protocol(submodule_add_args(url, path, branch, name, None, force));
+        self.run_top_level_args(&args, true)
+    }
+
+    /// Run the git command with arguments from one of the `*_args` functions
+    fn run_args(&self, args: &[String], print: bool) -> Result<(), GitError> {
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        self.run_git_command(&args, print)?;
         Ok(())
     }
+
+    /// Run the git command with arguments from one of the `*_args` functions in the top level
+    /// directory
+    fn run_top_level_args(&self, args: &[String], print: bool) -> Result<(), GitError> {
+        let top_level_dir = self.top_level_dir()?.to_cmd_arg();
+        let mut full_args = vec!["-C".to_string(), top_level_dir];
+        full_args.extend_from_slice(args);
+        self.run_args(&full_args, print)
+    }
+}
+
+/// Convert the arguments to owned strings
+fn to_args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|x| x.to_string()).collect()
+}
+
+/// Get the arguments of `git config -f <file> <key> <value>`, or
+/// `git config -f <file> --unset <key>` if `value` is [`None`]
+pub fn config_set_args<S>(config_path: S, key: &str, value: Option<&str>) -> Vec<String>
+where
+    S: AsRef<Path>,
+{
+    let config_path = config_path.to_cmd_arg();
+    match value {
+        Some(value) => to_args(&["config", "-f", &config_path, key, value]),
+        None => to_args(&["config", "-f", &config_path, "--unset", key]),
+    }
+}
+
+/// Get the arguments of `git config -f <file> --remove-section <section>`
+pub fn config_remove_section_args<S>(config_path: S, section: &str) -> Vec<String>
+where
+    S: AsRef<Path>,
+{
+    to_args(&[
+        "config",
+        "-f",
+        &config_path.to_cmd_arg(),
+        "--remove-section",
+        section,
+    ])
+}
+
+/// Get the arguments of `git rm <path>`
+pub fn rm_args(path: &str) -> Vec<String> {
+    to_args(&["rm", path])
+}
+
+/// Get the arguments of `git add <path>`
+pub fn add_args(path: &str) -> Vec<String> {
+    to_args(&["add", path])
+}
+
+/// Get the arguments of `git submodule deinit`, with `--all` if `path` is [`None`]
+pub fn submodule_deinit_args(path: Option<&str>, force: bool) -> Vec<String> {
+    let mut args = to_args(&["submodule", "deinit"]);
+    if force {
+        args.push("--force".to_string());
+    }
+    match path {
+        Some(path) => args.extend(to_args(&["--", path])),
+        None => args.push("--all".to_string()),
+    }
+    args
+}
+
+/// Get the arguments of `git submodule init`
+pub fn submodule_init_args(path: Option<&str>) -> Vec<String> {
+    let mut args = to_args(&["submodule", "init"]);
+    if let Some(path) = path {
+        args.extend(to_args(&["--", path]));
+    }
+    args
+}
+
+/// Get the arguments of `git submodule sync`
+pub fn submodule_sync_args(path: Option<&str>, recursive: bool) -> Vec<String> {
+    let mut args = to_args(&["submodule", "sync"]);
+    if recursive {
+        args.push("--recursive".to_string());
+    }
+    if let Some(path) = path {
+        args.extend(to_args(&["--", path]));
+    }
+    args
+}
+
+/// Get the arguments of `git submodule update`
+pub fn submodule_update_args(
+    paths: &[&str],
+    force: bool,
+    remote: bool,
+    recursive: bool,
+    jobs: Option<usize>,
+) -> Vec<String> {
+    let mut args = to_args(&["submodule", "update"]);
+    if force {
+        args.push("--force".to_string());
+    }
+    if remote {
+        args.push("--remote".to_string());
+    }
+    if recursive {
+        args.extend(to_args(&["--init", "--recursive"]));
+    }
+    if let Some(jobs) = jobs {
+        args.extend(["--jobs".to_string(), jobs.to_string()]);
+    }
+    if !paths.is_empty() {
+        args.push("--".to_string());
+        args.extend(to_args(paths));
+    }
+    args
+}
+
+/// Get the arguments of `git submodule absorbgitdirs`
+pub fn submodule_absorbgitdirs_args(path: Option<&str>) -> Vec<String> {
+    let mut args = to_args(&["submodule", "absorbgitdirs"]);
+    if let Some(path) = path {
+        args.extend(to_args(&["--", path]));
+    }
+    args
+}
+
+/// Get the arguments of `git submodule add`
+pub fn submodule_add_args(
+    url: &str,
+    path: Option<&str>,
+    branch: Option<&str>,
+    name: Option<&str>,
+    depth: Option<usize>,
+    force: bool,
+) -> Vec<String> {
+    let mut args = to_args(&["submodule", "add"]);
+    if force {
+        args.push("--force".to_string());
+    }
+    if let Some(branch) = branch {
+        args.extend(to_args(&["--branch", branch]));
+    }
+    if let Some(name) = name {
+        args.extend(to_args(&["--name", name]));
+    }
+    if let Some(depth) = depth {
+        args.extend(["--depth".to_string(), depth.to_string()]);
+    }
+    args.extend(to_args(&["--", url]));
+    if let Some(path) = path {
+        args.push(path.to_string());
+    }
+    args
+}
+
+/// Add `-c protocol.file.allow=always` before the arguments of a git command, which is needed
+/// to clone from local paths since git 2.38.1
+fn allow_file_protocol(args: Vec<String>) -> Vec<String> {
+    let mut result = to_args(&["-c", "protocol.file.allow=always"]);
+    result.extend(args);
+    result
 }
 
 /// Guard that uses file locking to ensure only one process are manipulating
@@ -569,6 +973,25 @@ impl Guard {
         println_verbose!("Acquired lock file `{}`", path.to_cmd_arg());
         Ok(Self(file, path.to_path_buf()))
     }
+
+    /// Check if the lock file is held by a running process. A lock file that exists but is not
+    /// held is left behind by a process that didn't exit cleanly
+    pub fn is_held<P>(path: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => return false,
+        };
+        match file.try_lock_exclusive() {
+            Ok(true) => {
+                let _ = <File as FileExt>::unlock(&file);
+                false
+            }
+            _ => true,
+        }
+    }
 }
 
 impl Drop for Guard {
@@ -622,6 +1045,40 @@ pub enum GitError {
 
     #[error("unsupported git version: {0}")]
     UnsupportedVersion(String),
+
+    #[error("submodule check failed: {}", .0.describe())]
+    CheckFailed(CheckFailure),
+
+    #[error("invalid version requirement `{0}`: {1}")]
+    InvalidVersionReq(String, semver::Error),
+
+    #[error("cannot find revision `{0}`")]
+    RevisionNotFound(String),
+
+    #[error("cannot read `{0}`: {1}")]
+    ReadFailed(String, std::io::Error),
+
+    #[error("cannot move `{0}` to `{1}`: {2}")]
+    RenameFailed(String, String, std::io::Error),
+
+    #[error("cannot write `{0}`: {1}")]
+    WriteFailed(String, std::io::Error),
+
+    #[cfg(feature = "serde")]
+    #[error("cannot process json: {0}")]
+    InvalidJson(serde_json::Error),
+
+    #[error("invalid manifest: {0}")]
+    InvalidManifest(String),
+
+    #[error("cannot find trash entry `{0}`")]
+    TrashNotFound(String),
+
+    #[error("path `{0}` is not inside the repository")]
+    PathOutsideRepo(String),
+
+    #[error("invalid trash entry `{0}`")]
+    InvalidTrashEntry(String),
 }
 
 /// Helper trait to canonicalize a path and return a [`GitError`] if failed
@@ -676,3 +1133,102 @@ pub fn quote_arg(s: &str) -> Cow<'_, str> {
         Cow::Borrowed(s)
     }
 }
+
+/// Resolve a relative submodule URL (starting with `./` or `../`) against the URL of the
+/// superproject's remote, the same way `git submodule init` does.
+///
+/// Returns [`None`] if the URL is not relative
+pub fn resolve_relative_url(base: &str, url: &str) -> Option<String> {
+    if !url.starts_with("./") && !url.starts_with("../") {
+        return None;
+    }
+    let mut base = base.trim_end_matches('/').to_string();
+    let mut url = url;
+    // `:` separates the host and path in scp-like URLs like `git@host:repo.git`
+    let mut separator = '/';
+    loop {
+        if let Some(rest) = url.strip_prefix("./") {
+            url = rest;
+        } else if let Some(rest) = url.strip_prefix("../") {
+            url = rest;
+            match base.rfind(['/', ':']) {
+                Some(i) => {
+                    separator = if base[i..].starts_with(':') { ':' } else { '/' };
+                    base.truncate(i);
+                }
+                None => {
+                    separator = '/';
+                    base = ".".to_string();
+                }
+            }
+        } else {
+            break;
+        }
+    }
+    Some(format!("{base}{separator}{url}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_submodule_add_args() {
+        let args = submodule_add_args("../a.git", Some("libs/a"), Some("main"), None, None, true);
+        assert_eq!(
+            args,
+            [
+                "submodule",
+                "add",
+                "--force",
+                "--branch",
+                "main",
+                "--",
+                "../a.git",
+                "libs/a"
+            ]
+        );
+        let args = allow_file_protocol(submodule_add_args("/tmp/a", None, None, None, None, false));
+        assert_eq!(
+            args,
+            [
+                "-c",
+                "protocol.file.allow=always",
+                "submodule",
+                "add",
+                "--",
+                "/tmp/a"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_relative_url() {
+        let resolve = |base, url| resolve_relative_url(base, url);
+        assert_eq!(resolve("https://github.com/a/b.git", "https://x/y"), None);
+        assert_eq!(
+            resolve("https://github.com/a/b.git", "../c.git").as_deref(),
+            Some("https://github.com/a/c.git")
+        );
+        assert_eq!(
+            resolve("https://github.com/a/b/", "./c").as_deref(),
+            Some("https://github.com/a/b/c")
+        );
+        assert_eq!(
+            resolve("https://github.com/a/b.git", "../../x/c.git").as_deref(),
+            Some("https://github.com/x/c.git")
+        );
+        assert_eq!(
+            resolve("git@github.com:a/b.git", "../c.git").as_deref(),
+            Some("git@github.com:a/c.git")
+        );
+        assert_eq!(
+            resolve("git@github.com:b.git", "../c.git").as_deref(),
+            Some("git@github.com:c.git")
+        );
+        assert_eq!(
+            resolve("/home/user/super", "../lib").as_deref(),
+            Some("/home/user/lib")
+        );
+    }
+}
@@ -19,18 +19,23 @@
 //! ### Examples
 //! #### Run a command
 //! ```rust
-//! use magoo::{StatusCommand, PrintOptions};
+//! use magoo::{StatusCommand, StatusFormat, PrintOptions};
 //!
 //! let command = magoo::StatusCommand {
 //!     git: true,
 //!     fix: false,
 //!     long: false,
+//!     recursive: false,
+//!     fetch: false,
 //!     options: PrintOptions {
 //!         verbose: false,
 //!         quiet: false,
 //!         color: None,
 //!     },
 //!     delete: false,
+//!     dry_run: false,
+//!     check: false,
+//!     format: StatusFormat::Text,
 //! };
 //!
 //! // don't need this if you don't need output to stdout
@@ -44,7 +49,7 @@
 //! use clap::Parser;
 //!
 //! // for assertion below only
-//! use magoo::{Command, StatusCommand, PrintOptions};
+//! use magoo::{Command, StatusCommand, StatusFormat, PrintOptions};
 //!
 //! let magoo = Magoo::try_parse_from(["magoo", "--dir", "my/repo", "status", "--long", "--verbose"]).unwrap();
 //!
@@ -53,12 +58,17 @@
 //!         git: false,
 //!         fix: false,
 //!         long: true,
+//!         recursive: false,
+//!         fetch: false,
 //!         options: PrintOptions {
 //!             verbose: true,
 //!             quiet: false,
 //!             color: None,
 //!         },
 //!         delete: false,
+//!         dry_run: false,
+//!         check: false,
+//!         format: StatusFormat::Text,
 //!     }),
 //!     dir: "my/repo".to_string(),
 //!     common: Default::default(),
@@ -71,16 +81,35 @@
 //! reference.
 //!
 
+use std::path::Path;
+
+pub mod check;
+pub mod diff;
+pub mod foreach;
 pub mod git;
-use git::{GitContext, GitError};
+#[cfg(feature = "serde")]
+pub mod manifest;
+pub mod plan;
+use diff::SubmoduleDiff;
+use foreach::ForeachJob;
+use git::{GitCanonicalize, GitCmdPath, GitContext, GitError, Guard, quote_arg};
+#[cfg(feature = "serde")]
+use manifest::{Manifest, ManifestFormat};
+use plan::Action;
+use trash::Trash;
 
 pub mod print;
 pub mod status;
 pub mod submodule;
+pub mod trash;
 pub mod version;
-use status::Status;
+use status::{Status, StatusTree};
+use submodule::{CheckFailure, Submodule};
 
-use crate::print::{println_error, println_hint, println_info, println_verbose, println_warn};
+use crate::print::{
+    eprint_info, print_info, print_warn, println_error, println_hint, println_info,
+    println_verbose, println_warn,
+};
 
 /// The main entry point for the library
 #[derive(Debug, Clone, PartialEq)]
@@ -91,7 +120,7 @@ use crate::print::{println_error, println_hint, println_info, println_verbose, p
 )]
 pub struct Magoo {
     /// Command to run
-    #[clap(subcommand)]
+    #[cfg_attr(feature = "cli", clap(subcommand))]
     pub subcmd: Command,
     /// Set the working directory of commands. Useful if not running inside a git repository.
     #[cfg_attr(feature = "cli", clap(long, short('C'), default_value(".")))]
@@ -131,6 +160,89 @@ pub enum Command {
     Update(UpdateCommand),
     /// Remove a dependency
     Remove(RemoveCommand),
+    /// List dependencies that have new commits upstream
+    ///
+    /// The branch of each dependency (or `HEAD` if no branch was specified when adding) is
+    /// fetched from the remote and compared with the commit in the index.
+    Outdated(OutdatedCommand),
+    /// Show the changes to the submodules between two revisions of the current repository
+    ///
+    /// Lists the submodules added, removed, moved, or pointed to a different commit, and
+    /// changes to the URL and update branch.
+    Diff(DiffCommand),
+    /// Show the commits between the commit in the index and the one checked out (or upstream)
+    /// for a dependency
+    Log(LogCommand),
+    /// Generate a Markdown changelog for the dependencies changed since a revision
+    ///
+    /// Only changes staged in the index are included.
+    Changelog(ChangelogCommand),
+    /// Export the dependencies to a manifest file
+    ///
+    /// The manifest records the name, path, URL (as in .gitmodules), branch, commit in the index,
+    /// and the shallow setting of each dependency.
+    #[cfg(feature = "serde")]
+    Export(ExportCommand),
+    /// Add the dependencies in a manifest file created with `magoo export`
+    ///
+    /// Each dependency is added if it doesn't exist, and pinned to the commit in the manifest.
+    #[cfg(feature = "serde")]
+    Import(ImportCommand),
+    /// Move a dependency to a new path
+    ///
+    /// The worktree is moved, and the path in .gitmodules, the index and
+    /// `.git/modules/<name>/config` are updated.
+    Move(MoveCommand),
+    /// Change the name of a dependency
+    ///
+    /// The name is changed in .gitmodules, .git/config and .git/modules, without cloning the
+    /// dependency again.
+    Rename(RenameCommand),
+    /// Run a command in every initialized dependency
+    ///
+    /// The command is run with the shell in the worktree of each dependency, with `$name`,
+    /// `$sm_path`, `$sha` and `$toplevel` set in the environment. Use `$sm_path` in portable
+    /// scripts: `$path` is also set, but not on Windows.
+    Foreach(ForeachCommand),
+    /// Set a dependency to an exact commit, tag or branch
+    ///
+    /// The revision is fetched from the remote if needed (always for branches), checked out in
+    /// the dependency, and staged in the index.
+    Pin(PinCommand),
+    /// Turn a dependency into regular files tracked in the current repository
+    ///
+    /// The submodule is removed, but the files in its worktree are kept and added to the index.
+    Vendor(VendorCommand),
+    /// Move a directory of the current repository into a new repository, and add it back as a
+    /// dependency
+    ///
+    /// The history of the directory is split with `git subtree split` and pushed to the URL.
+    Extract(ExtractCommand),
+    /// Register git repositories in the worktree that are not submodules, like repositories
+    /// cloned by hand
+    ///
+    /// Lists the repositories if no paths are provided.
+    Adopt(AdoptCommand),
+    /// Move the git directories embedded in the worktrees of dependencies into .git/modules
+    ///
+    /// This happens for dependencies cloned by older tools, and is also fixed by
+    /// `magoo status --fix`.
+    Absorb(AbsorbCommand),
+    /// Stage and commit the dependencies that have a different commit checked out from the index
+    ///
+    /// The commit message is generated from the old and new commit of each dependency.
+    Commit(CommitCommand),
+    /// Print diagnostics of the environment and the current git repository
+    ///
+    /// Include the output when reporting a problem with magoo.
+    Doctor(DoctorCommand),
+    /// List, restore or empty the directories removed by magoo
+    ///
+    /// Worktrees and `.git/modules/<name>` directories removed by commands like `remove` and
+    /// `status --fix` are moved to `.git/magoo-trash` instead of being deleted. `remove` without
+    /// `--force` empties the worktree with `git submodule deinit` first, so only
+    /// `.git/modules/<name>` is kept.
+    Trash(TrashCommand),
 }
 
 impl Command {
@@ -141,6 +253,25 @@ impl Command {
             Command::Install(cmd) => cmd.set_print_options(),
             Command::Update(cmd) => cmd.set_print_options(),
             Command::Remove(cmd) => cmd.set_print_options(),
+            Command::Outdated(cmd) => cmd.set_print_options(),
+            Command::Diff(cmd) => cmd.set_print_options(),
+            Command::Log(cmd) => cmd.set_print_options(),
+            Command::Changelog(cmd) => cmd.set_print_options(),
+            #[cfg(feature = "serde")]
+            Command::Export(cmd) => cmd.set_print_options(),
+            #[cfg(feature = "serde")]
+            Command::Import(cmd) => cmd.set_print_options(),
+            Command::Move(cmd) => cmd.set_print_options(),
+            Command::Rename(cmd) => cmd.set_print_options(),
+            Command::Foreach(cmd) => cmd.set_print_options(),
+            Command::Pin(cmd) => cmd.set_print_options(),
+            Command::Vendor(cmd) => cmd.set_print_options(),
+            Command::Extract(cmd) => cmd.set_print_options(),
+            Command::Adopt(cmd) => cmd.set_print_options(),
+            Command::Absorb(cmd) => cmd.set_print_options(),
+            Command::Commit(cmd) => cmd.set_print_options(),
+            Command::Doctor(cmd) => cmd.set_print_options(),
+            Command::Trash(cmd) => cmd.set_print_options(),
         }
     }
 
@@ -159,6 +290,59 @@ impl Command {
             Command::Remove(cmd) => {
                 cmd.run(dir, common)?;
             }
+            Command::Outdated(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Diff(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Log(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Changelog(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            #[cfg(feature = "serde")]
+            Command::Export(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            #[cfg(feature = "serde")]
+            Command::Import(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Move(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Rename(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Foreach(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Pin(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Vendor(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Extract(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Adopt(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Absorb(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Commit(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Doctor(cmd) => {
+                cmd.run(dir, common)?;
+            }
+            Command::Trash(cmd) => {
+                cmd.run(dir, common)?;
+            }
         }
 
         Ok(())
@@ -177,6 +361,19 @@ pub struct StatusCommand {
     #[cfg_attr(feature = "cli", clap(long, short))]
     pub long: bool,
 
+    /// Also show the status of nested submodules in every initialized submodule
+    ///
+    /// Nested submodules are shown indented under their parent.
+    #[cfg_attr(feature = "cli", clap(long, short, conflicts_with("fix")))]
+    pub recursive: bool,
+
+    /// Fetch the tracked branch of the submodules before showing the status
+    ///
+    /// By default, the commits ahead/behind the tracked branch are counted against the
+    /// remote-tracking refs that are already fetched.
+    #[cfg_attr(feature = "cli", clap(long, conflicts_with("fix")))]
+    pub fetch: bool,
+
     /// Fix the submodules to be in a consistent state. (CAUTION - you should never have to do this if you let magoo manage the submodules, be sure to read the details in `magoo status --help` before using!)
     ///
     /// If any submodule appears to be broken (likely due to changing
@@ -186,6 +383,7 @@ pub struct StatusCommand {
     /// USE WITH CAUTION - If the submodule state is so broken that there's not enough information
     /// to fix it, it will be removed from existence.
     /// This may delete local files and directories that look like submodules because they are referenced by git files.
+    /// Deleted directories are moved to `.git/magoo-trash`, see `magoo trash`.
     ///
     #[cfg_attr(feature = "cli", clap(long, short))]
     pub fix: bool,
@@ -194,11 +392,51 @@ pub struct StatusCommand {
     #[cfg_attr(feature = "cli", clap(long, requires("fix")))]
     pub delete: bool,
 
+    /// Print the git commands and deletions that fixing would do, without changing anything
+    #[cfg_attr(feature = "cli", clap(long, requires("fix")))]
+    pub dry_run: bool,
+
+    /// Only check the submodules and exit with a non-zero code if any problem is found
+    ///
+    /// Only the problems are printed. If there are multiple problems, the exit code
+    /// is for the most severe one (lowest code):
+    ///
+    /// - 10: submodule has residue in `.git/modules`
+    /// - 11: inconsistent paths
+    /// - 12: inconsistent state (has residue from removal)
+    /// - 13: inconsistent state (missing in index)
+    /// - 14: inconsistent state (not in .gitmodules)
+    /// - 15: inconsistent state (missing in index and .gitmodules)
+    /// - 16: not initialized
+    /// - 17: checked out commit is different from index
+    #[cfg_attr(feature = "cli", clap(long, conflicts_with_all(["fix", "format"])))]
+    pub check: bool,
+
+    /// Format of the status output
+    ///
+    /// `json` prints the full status of every submodule as a JSON object to stdout, which is
+    /// meant to be consumed by other tools. Ignored when `--fix` is specified, and cannot be
+    /// used with `--check`.
+    #[cfg_attr(feature = "cli", clap(long, value_enum, default_value = "text"))]
+    pub format: StatusFormat,
+
     /// Print options
     #[cfg_attr(feature = "cli", clap(flatten))]
     pub options: PrintOptions,
 }
 
+/// Output format of the `status` command
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum StatusFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// JSON, see [`StatusReport`](status::StatusReport) for the schema
+    #[cfg(feature = "serde")]
+    Json,
+}
+
 impl StatusCommand {
     /// Apply the print options
     pub fn set_print_options(&self) {
@@ -217,29 +455,116 @@ impl StatusCommand {
         }
         let _guard = context.lock()?;
 
-        let mut status = Status::read_from(&context)?;
-        let mut flat_status = status.flattened_mut();
-        if flat_status.is_empty() {
-            println!("No submodules found");
-            return Ok(status);
-        }
         if self.fix {
+            let mut status = Status::read_from(&context)?;
+            let mut flat_status = status.flattened_mut();
+            if flat_status.is_empty() {
+                println!("No submodules found");
+                return Ok(status);
+            }
+            if self.dry_run {
+                print_dry_run_header(&context)?;
+                for submodule in flat_status.iter_mut() {
+                    let name = submodule
+                        .name()
+                        .or(submodule.path())
+                        .unwrap_or("<unknown>")
+                        .to_string();
+                    let actions = submodule.plan_fix(&context, self.delete)?;
+                    print_submodule_plan(&name, &actions);
+                }
+                return Ok(status);
+            }
             for submodule in flat_status.iter_mut() {
                 submodule.fix(&context, self.delete)?;
             }
             return Ok(status);
         }
 
+        let tree = if self.recursive {
+            Status::read_recursive(&context)?
+        } else {
+            StatusTree::from(Status::read_from(&context)?)
+        };
+        if self.fetch {
+            tree.walk(&context, &mut |context, submodule, _| {
+                if let Err(e) = submodule.fetch_upstream(context) {
+                    println_warn!(
+                        "Failed to fetch submodule `{}`: {e}",
+                        submodule.name().unwrap_or_default()
+                    );
+                }
+                Ok(())
+            })?;
+        }
+        #[cfg(feature = "serde")]
+        if self.format == StatusFormat::Json {
+            let report = tree.report(&context)?;
+            let json = serde_json::to_string_pretty(&report).map_err(GitError::InvalidJson)?;
+            println!("{json}");
+            return Ok(tree.status);
+        }
+        if self.check {
+            return Self::check(&context, tree);
+        }
+        if tree.status.flattened().is_empty() {
+            println!("No submodules found");
+            return Ok(tree.status);
+        }
+
         let dir_switch = if dir == "." {
             "".to_string()
         } else {
             format!(" --dir {dir}")
         };
 
-        for submodule in &flat_status {
-            submodule.print(&context, &dir_switch, self.long)?;
+        tree.walk(&context, &mut |sub_context, submodule, depth| {
+            if depth == 0 {
+                return submodule.print(sub_context, &dir_switch, self.long);
+            }
+            let dir_switch = match sub_context.get_top_level_switch()? {
+                Some(x) => format!(" --dir {x}"),
+                None => "".to_string(),
+            };
+            let indent = "    ".repeat(depth);
+            submodule.print_indented(sub_context, &dir_switch, self.long, &indent)
+        })?;
+        Ok(tree.status)
+    }
+
+    /// Print the problems of the submodules and return [`GitError::CheckFailed`] with the most
+    /// severe one
+    fn check(context: &GitContext, tree: StatusTree) -> Result<Status, GitError> {
+        let top_level_dir = context.top_level_dir()?;
+        let mut most_severe: Option<CheckFailure> = None;
+        tree.walk(context, &mut |sub_context, submodule, depth| {
+            let mut name = submodule
+                .name()
+                .or(submodule.path())
+                .unwrap_or("<unknown>")
+                .to_string();
+            if depth > 0
+                && let Some(parent) =
+                    pathdiff::diff_paths(sub_context.top_level_dir()?, top_level_dir)
+            {
+                name = format!("{name} (in {})", parent.to_cmd_arg());
+            }
+            for failure in submodule.check(sub_context)? {
+                println_error!("{name}: {}", failure.describe());
+                most_severe = Some(match most_severe {
+                    Some(x) => x.min(failure),
+                    None => failure,
+                });
+            }
+            Ok(())
+        })?;
+        match most_severe {
+            Some(failure) => Err(GitError::CheckFailed(failure)),
+            None => {
+                println_info!("All submodules are OK");
+                Ok(tree.status)
+            }
         }
-        Ok(status)
     }
 }
 
@@ -293,6 +618,22 @@ pub struct InstallCommand {
     #[cfg_attr(feature = "cli", clap(long))]
     pub no_recursive: bool,
 
+    /// Number of submodules to fetch and clone at the same time when installing
+    ///
+    /// This will pass the `--jobs` flag to `git submodule update`. If not specified, the
+    /// `submodule.fetchJobs` config is used, which defaults to 1. Progress is shown by the
+    /// output of git for each submodule as it is cloned and checked out.
+    #[cfg_attr(feature = "cli", clap(long, short))]
+    #[cfg_attr(feature = "cli", arg(conflicts_with("url")))]
+    pub jobs: Option<usize>,
+
+    /// Print the git commands and deletions that installing would do, without changing
+    /// anything
+    ///
+    /// Broken submodules are fixed before installing, which may delete files.
+    #[cfg_attr(feature = "cli", clap(long))]
+    pub dry_run: bool,
+
     /// Print options
     #[cfg_attr(feature = "cli", clap(flatten))]
     pub options: PrintOptions,
@@ -313,30 +654,52 @@ impl InstallCommand {
         let _guard = context.lock()?;
 
         let mut status = Status::read_from(&context)?;
-        for submodule in status.flattened_mut() {
-            submodule.fix(&context, false)?;
-        }
+        let actions = match &self.url {
+            Some(url) => vec![Action::SubmoduleAdd {
+                url: url.clone(),
+                path: self.path.clone(),
+                branch: self.branch.clone(),
+                name: self.name.clone(),
+                depth: self.depth,
+                force: self.force,
+            }],
+            None => vec![
+                Action::SubmoduleInit,
+                Action::SubmoduleSync {
+                    recursive: !self.no_recursive,
+                },
+                Action::SubmoduleUpdate {
+                    force: self.force,
+                    recursive: !self.no_recursive,
+                    jobs: self.jobs,
+                },
+            ],
+        };
 
-        match &self.url {
-            Some(url) => {
-                println_verbose!("Adding submodule from url: {url}");
-                context.submodule_add(
-                    url,
-                    self.path.as_deref(),
-                    self.branch.as_deref(),
-                    self.name.as_deref(),
-                    self.depth.as_ref().copied(),
-                    self.force,
-                )?;
-            }
-            None => {
-                println_verbose!("Installing submodules");
-                context.submodule_init(None)?;
-                context.submodule_sync(None, !self.no_recursive)?;
-                context.submodule_update(None, self.force, false, !self.no_recursive)?;
+        if self.dry_run {
+            print_dry_run_header(&context)?;
+            for submodule in status.flattened_mut() {
+                let fix_actions = submodule.plan_fix(&context, false)?;
+                if !fix_actions.is_empty() {
+                    let name = submodule
+                        .name()
+                        .or(submodule.path())
+                        .unwrap_or("<unknown>")
+                        .to_string();
+                    print_submodule_plan(&name, &fix_actions);
+                }
             }
+            println_info!("Install:");
+            plan::print_actions(&actions, "  ");
+            return Ok(());
         }
 
+        for submodule in status.flattened_mut() {
+            submodule.fix(&context, false)?;
+        }
+        println_verbose!("Installing submodules");
+        plan::run_actions(&context, &actions)?;
+
         Ok(())
     }
 }
@@ -365,9 +728,24 @@ pub struct UpdateCommand {
     #[cfg_attr(feature = "cli", arg(requires("name")))]
     pub url: Option<String>,
 
+    /// Track the highest release tag that satisfies a semver requirement, like `^1.4`
+    ///
+    /// The requirement is stored as `magoo-version` in .gitmodules. Submodules with a
+    /// requirement are updated to the highest matching tag (with an optional leading `v`)
+    /// instead of the tip of the branch.
+    #[cfg_attr(feature = "cli", clap(long))]
+    #[cfg_attr(feature = "cli", arg(requires("name")))]
+    pub semver: Option<String>,
+
+    /// Stop tracking release tags and update from the branch again
+    #[cfg_attr(feature = "cli", clap(long))]
+    #[cfg_attr(feature = "cli", arg(requires("name"), conflicts_with("semver")))]
+    pub unset_semver: bool,
+
     /// Whether to force the submodule to be updated
     ///
-    /// This will pass the `--force` flag to `git submodule update`.
+    /// This will pass the `--force` flag to `git submodule update`, and update submodules
+    /// with uncommitted changes (the changes may be lost).
     #[cfg_attr(feature = "cli", clap(long, short))]
     pub force: bool,
 
@@ -375,6 +753,15 @@ pub struct UpdateCommand {
     #[cfg_attr(feature = "cli", clap(long))]
     pub bypass: bool,
 
+    /// Number of submodules to fetch at the same time when updating all submodules
+    ///
+    /// This will pass the `--jobs` flag to `git submodule update`. If not specified, the
+    /// `submodule.fetchJobs` config is used, which defaults to 1. Progress is shown by the
+    /// output of git for each submodule as it is fetched and checked out.
+    #[cfg_attr(feature = "cli", clap(long))]
+    #[cfg_attr(feature = "cli", arg(conflicts_with("name")))]
+    pub jobs: Option<usize>,
+
     /// Print options
     #[cfg_attr(feature = "cli", clap(flatten))]
     pub options: PrintOptions,
@@ -397,44 +784,11 @@ impl UpdateCommand {
         match &self.name {
             Some(name) => {
                 println_verbose!("Updating submodule: {name}");
-                let status = Status::read_from(&context)?;
-                let submodule = match status.modules.get(name) {
-                    Some(submodule) => submodule,
-                    None => {
-                        println_error!("Submodule `{name}` not found!");
-                        // maybe user passed in path instead of name?
-                        println_verbose!("Trying to search for a path matching `{name}`");
-                        for submodule in status.flattened() {
-                            if let Some(other_name) = submodule.name() {
-                                if let Some(path) = submodule.path() {
-                                    if path == name {
-                                        println_hint!(
-                                            "  however, there is a submodule \"{other_name}\" with path \"{path}\""
-                                        );
-                                        println_hint!(
-                                            "  if you meant to update this submodule, use `magoo update {other_name}`"
-                                        );
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-
-                        return Err(GitError::NeedFix(false));
-                    }
-                };
-                if !submodule.is_healthy(&context)? {
-                    if !self.bypass {
-                        println_error!("Submodule `{name}` is not healthy!");
-                        println_hint!(
-                            "  run `magoo status` to investigate. Some issues might be fixable with `magoo status --fix`."
-                        );
-                        println_hint!(
-                            "  alternatively, use the `--bypass` flag to ignore and continue anyway."
-                        );
-                        return Err(GitError::NeedFix(false));
-                    }
-                    println_warn!("Bypassing warnings from unhealthy submodule `{name}`");
+                let mut status = Status::read_from(&context)?;
+                let submodule = &*find_submodule(&mut status, name, "update")?;
+                check_healthy(&context, submodule, name, self.bypass)?;
+                if !self.force && is_dirty(&context, submodule)? {
+                    return Err(GitError::NeedFix(false));
                 }
 
                 let path = match submodule.path() {
@@ -459,22 +813,85 @@ impl UpdateCommand {
                     context.submodule_set_url(path, url)?;
                 }
 
+                let key = format!("submodule.{name}.magoo-version");
+                let dot_gitmodules = context.top_level_dir()?.join(".gitmodules");
+                let version_req = if self.unset_semver {
+                    if submodule.version_req().is_some() {
+                        context.set_config(&dot_gitmodules, &key, None)?;
+                        context.add(".gitmodules")?;
+                    }
+                    None
+                } else if let Some(version_req) = &self.semver {
+                    if let Err(e) = semver::VersionReq::parse(version_req) {
+                        return Err(GitError::InvalidVersionReq(version_req.to_string(), e));
+                    }
+                    context.set_config(&dot_gitmodules, &key, Some(version_req))?;
+                    context.add(".gitmodules")?;
+                    Some(version_req.as_str())
+                } else {
+                    submodule.version_req()
+                };
+
                 context.submodule_sync(Some(path), false)?;
-                context.submodule_update(Some(path), self.force, true, false)?;
+                if version_req.is_some() {
+                    context.submodule_update(&[path], self.force, false, false, None)?;
+                    update_to_versions(&context, Some(name))?;
+                } else {
+                    context.submodule_update(&[path], self.force, true, false, None)?;
+                }
             }
             None => {
                 println_verbose!("Updating submodules");
+                if !self.force {
+                    let status = Status::read_from(&context)?;
+                    let mut has_dirty = false;
+                    for submodule in status.flattened() {
+                        has_dirty |= is_dirty(&context, submodule)?;
+                    }
+                    if has_dirty {
+                        return Err(GitError::NeedFix(false));
+                    }
+                }
                 context.submodule_init(None)?;
                 context.submodule_sync(None, false)?;
-                context.submodule_update(None, self.force, true, false)?;
+                // submodules tracking a semver requirement are not updated from the branch
+                let status = Status::read_from(&context)?;
+                let submodules = status.flattened();
+                if submodules.iter().any(|x| x.version_req().is_some()) {
+                    let (version_paths, branch_paths): (Vec<_>, Vec<_>) = submodules
+                        .iter()
+                        .filter_map(|x| Some((x.path()?, x.version_req().is_some())))
+                        .partition(|(_, has_version)| *has_version);
+                    let version_paths = version_paths.into_iter().map(|x| x.0).collect::<Vec<_>>();
+                    let branch_paths = branch_paths.into_iter().map(|x| x.0).collect::<Vec<_>>();
+                    if !branch_paths.is_empty() {
+                        context.submodule_update(
+                            &branch_paths,
+                            self.force,
+                            true,
+                            false,
+                            self.jobs,
+                        )?;
+                    }
+                    // make sure the submodules are initialized before checking out the tags
+                    context.submodule_update(
+                        &version_paths,
+                        self.force,
+                        false,
+                        false,
+                        self.jobs,
+                    )?;
+                    update_to_versions(&context, None)?;
+                } else {
+                    context.submodule_update(&[], self.force, true, false, self.jobs)?;
+                }
             }
         }
 
         println_info!();
         println_info!("Submodules updated successfully.");
-        println_hint!(
-            "  run `git status` to check the changes and run `git add ...` to stage them"
-        );
+        println_hint!("  run `git status` to check the changes");
+        println_hint!("  run `magoo commit` to stage and commit the updated submodules");
         println_hint!("  run `magoo status` to check the status of the submodules");
         Ok(())
     }
@@ -488,6 +905,8 @@ pub struct RemoveCommand {
     pub name: String,
 
     /// Force remove the submodule. Will delete any local changes to the submodule
+    ///
+    /// Without this flag, submodules with uncommitted changes will not be removed.
     #[cfg_attr(feature = "cli", clap(long, short))]
     pub force: bool,
 
@@ -498,6 +917,10 @@ pub struct RemoveCommand {
     #[cfg_attr(feature = "cli", arg(conflicts_with("force")))]
     pub force_deinit: bool,
 
+    /// Print the git commands and deletions that removing would do, without changing anything
+    #[cfg_attr(feature = "cli", clap(long))]
+    pub dry_run: bool,
+
     /// Print options
     #[cfg_attr(feature = "cli", clap(flatten))]
     pub options: PrintOptions,
@@ -521,38 +944,19 @@ impl RemoveCommand {
 
         println_verbose!("Removing submodule: {name}");
         let mut status = Status::read_from(&context)?;
-        let submodule = match status.modules.get_mut(name) {
-            Some(submodule) => submodule,
-            None => {
-                println_error!("Submodule `{name}` not found!");
-                // maybe user passed in path instead of name?
-                println_verbose!("Trying to search for a path matching `{name}`");
-                for submodule in status.flattened() {
-                    if let Some(other_name) = submodule.name() {
-                        if let Some(path) = submodule.path() {
-                            if path == name {
-                                println_hint!(
-                                    "  however, there is a submodule \"{other_name}\" with path \"{path}\""
-                                );
-                                println_hint!(
-                                    "  if you meant to remove this submodule, use `magoo remove {other_name}`"
-                                );
-                                break;
-                            }
-                        }
-                    }
-                }
+        let submodule = find_submodule(&mut status, name, "remove")?;
 
-                return Err(GitError::NeedFix(false));
-            }
-        };
+        if !self.force && is_dirty(&context, submodule)? {
+            return Err(GitError::NeedFix(false));
+        }
 
+        let mut actions = Vec::new();
         if self.force {
             println_verbose!("Removing (force): {name}");
-            submodule.force_delete(&context)?;
+            actions.extend(submodule.plan_delete(&context)?);
         } else {
             let path = match submodule.path() {
-                Some(x) => x,
+                Some(x) => x.to_string(),
                 None => {
                     println_error!("Submodule `{name}` does not have a path!");
                     println_hint!("  run `magoo status` to investigate.");
@@ -562,21 +966,36 @@ impl RemoveCommand {
                     return Err(GitError::NeedFix(false));
                 }
             };
-            if let Err(e) = context.submodule_deinit(Some(path), self.force_deinit) {
-                println_error!("Failed to deinitialize submodule `{name}`: {e}");
-                println_hint!(
-                    "  try running with `--force-deinit` to force deinitialize the module"
-                );
-                println_hint!(
-                    "  alternatively, running with `--force` will remove the module anyway."
-                );
-                return Err(GitError::NeedFix(false));
-            }
+            actions.push(Action::SubmoduleDeinit {
+                path,
+                force: self.force_deinit,
+            });
+            actions.extend(submodule.plan_remove_module_dir(&context)?);
+            actions.extend(submodule.plan_remove_config(&context)?);
+            actions.extend(submodule.plan_remove_from_dot_gitmodules(&context)?);
+            actions.extend(submodule.plan_remove_from_index(&context)?);
+            plan::dedup_adds(&mut actions);
+        }
 
-            submodule.force_remove_module_dir(&context)?;
-            submodule.force_remove_config(&context)?;
-            submodule.force_remove_from_dot_gitmodules(&context)?;
-            submodule.force_remove_from_index(&context)?;
+        if self.dry_run {
+            print_dry_run_header(&context)?;
+            print_submodule_plan(name, &actions);
+            return Ok(());
+        }
+        for action in &actions {
+            if let Err(e) = action.run(&context) {
+                if let Action::SubmoduleDeinit { .. } = action {
+                    println_error!("Failed to deinitialize submodule `{name}`: {e}");
+                    println_hint!(
+                        "  try running with `--force-deinit` to force deinitialize the module"
+                    );
+                    println_hint!(
+                        "  alternatively, running with `--force` will remove the module anyway."
+                    );
+                    return Err(GitError::NeedFix(false));
+                }
+                return Err(e);
+            }
         }
 
         println_info!();
@@ -586,6 +1005,1974 @@ impl RemoveCommand {
     }
 }
 
+/// The `outdated` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct OutdatedCommand {
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl OutdatedCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<(), GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let status = Status::read_from(&context)?;
+        let mut outdated_count = 0;
+        for submodule in status.flattened() {
+            let name = match submodule.name() {
+                Some(name) => format!("\"{name}\""),
+                None => "<unknown>".to_string(),
+            };
+            let index_commit = match submodule.index_commit() {
+                Some(x) => x,
+                None => continue,
+            };
+            print_info!("{name:<15}");
+            let sub_context = match submodule.open(&context) {
+                Some(x) => x,
+                None => {
+                    println_warn!(" not initialized, skipped");
+                    continue;
+                }
+            };
+            let latest = match submodule.fetch_latest(&context) {
+                Ok(Some(x)) => x,
+                Ok(None) => {
+                    println_warn!(" cannot find the latest commit");
+                    continue;
+                }
+                Err(e) => {
+                    println_error!(" failed to fetch: {e}");
+                    continue;
+                }
+            };
+
+            print_info!(" {}", &index_commit[..7]);
+            if let Some(describe) = sub_context.describe(index_commit) {
+                print_info!(" ({describe})");
+            }
+            if latest == index_commit {
+                println_info!(" is up to date");
+                continue;
+            }
+            let counts = sub_context.count_left_right(index_commit, &latest);
+            if let Ok((ahead, 0)) = counts {
+                println_info!(" is up to date, {ahead} commit(s) not in upstream");
+                continue;
+            }
+            outdated_count += 1;
+            print_info!(" -> {}", &latest[..7]);
+            if let Some(describe) = sub_context.describe(&latest) {
+                print_info!(" ({describe})");
+            }
+            match counts {
+                Ok((0, behind)) => {
+                    println_warn!(", {behind} new commit(s)");
+                }
+                Ok((ahead, behind)) => {
+                    println_warn!(", {behind} new commit(s), {ahead} commit(s) not in upstream");
+                }
+                Err(e) => {
+                    println_verbose!("Failed to count commits: {e}");
+                    println_info!();
+                }
+            }
+        }
+
+        println_info!();
+        if outdated_count == 0 {
+            println_info!("All submodules are up to date.");
+        } else {
+            println_info!("{outdated_count} submodule(s) have new commits upstream.");
+            println_hint!("  run `magoo update` to update all submodules");
+        }
+        Ok(())
+    }
+}
+
+/// The `diff` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct DiffCommand {
+    /// The old revision of the current repository, for example `HEAD~1` or `main`
+    pub old: String,
+
+    /// The new revision of the current repository
+    ///
+    /// If not specified, the index (staged changes) is used.
+    pub new: Option<String>,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl DiffCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory and return the changes
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<Vec<SubmoduleDiff>, GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let old = Status::read_from_revision(&context, Some(&self.old))?;
+        let new = Status::read_from_revision(&context, self.new.as_deref())?;
+        let diffs = SubmoduleDiff::compute(&old, &new);
+        let new_name = self.new.as_deref().unwrap_or("index");
+        if diffs.is_empty() {
+            println_info!("No submodule changes from {} to {new_name}", self.old);
+            return Ok(diffs);
+        }
+        // the worktrees are used to describe the commits
+        let status = Status::read_from(&context)?;
+        for diff in &diffs {
+            let name = &diff.name;
+            let sub_context = status.find(name).and_then(|x| x.open(&context));
+            let describe = |commit: &str| {
+                let short = &commit[..7];
+                match sub_context.as_ref().and_then(|x| x.describe(commit)) {
+                    Some(describe) => format!("{short} ({describe})"),
+                    None => short.to_string(),
+                }
+            };
+
+            if let (true, Some(new)) = (diff.is_added(), &diff.new) {
+                print_info!("+ \"{name}\"");
+                if let Some(path) = new.path() {
+                    print_info!(" at \"{path}\"");
+                }
+                if let Some(commit) = new.index_commit() {
+                    print_info!(" {}", describe(commit));
+                }
+                println_info!();
+                continue;
+            }
+            if let (true, Some(old)) = (diff.is_removed(), &diff.old) {
+                print_warn!("- \"{name}\"");
+                if let Some(path) = old.path() {
+                    print_warn!(" at \"{path}\"");
+                }
+                if let Some(commit) = old.index_commit() {
+                    print_warn!(" {}", &commit[..7]);
+                }
+                println_info!();
+                continue;
+            }
+            println_info!("~ \"{name}\"");
+            if let Some((old_path, new_path)) = diff.moved() {
+                println_info!("    moved \"{old_path}\" -> \"{new_path}\"");
+            }
+            if let Some((old_commit, new_commit)) = diff.repointed() {
+                print_info!(
+                    "    commit {} -> {}",
+                    describe(old_commit),
+                    describe(new_commit)
+                );
+                match sub_context
+                    .as_ref()
+                    .map(|x| x.count_left_right(old_commit, new_commit))
+                {
+                    Some(Ok((0, added))) => println_info!(", {added} new commit(s)"),
+                    Some(Ok((removed, added))) => {
+                        println_warn!(", {added} new commit(s), {removed} commit(s) removed")
+                    }
+                    _ => println_info!(),
+                }
+            }
+            if let Some((old_url, new_url)) = diff.url_changed() {
+                println_info!(
+                    "    url {} -> {}",
+                    old_url.unwrap_or("<none>"),
+                    new_url.unwrap_or("<none>")
+                );
+            }
+            if let Some((old_branch, new_branch)) = diff.branch_changed() {
+                println_info!(
+                    "    branch {} -> {}",
+                    old_branch.unwrap_or("<none>"),
+                    new_branch.unwrap_or("<none>")
+                );
+            }
+        }
+
+        Ok(diffs)
+    }
+}
+
+/// The `log` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct LogCommand {
+    /// Name of the submodule
+    pub name: String,
+
+    /// Compare with the tip of the update branch on the remote instead of the commit checked out
+    ///
+    /// If the submodule doesn't have an update branch, `HEAD` of the remote is used.
+    #[cfg_attr(feature = "cli", clap(long, short))]
+    pub upstream: bool,
+
+    /// Fetch from the remote before comparing with upstream
+    #[cfg_attr(feature = "cli", clap(long, requires("upstream")))]
+    pub fetch: bool,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl LogCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<(), GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let name = &self.name;
+        let mut status = Status::read_from(&context)?;
+        let submodule = &*find_submodule(&mut status, name, "log")?;
+        let index_commit = match submodule.index_commit() {
+            Some(x) => x,
+            None => {
+                println_error!("Submodule `{name}` is not in the index!");
+                println_hint!("  run `magoo status` to investigate.");
+                return Err(GitError::NeedFix(false));
+            }
+        };
+        let sub_context = match submodule.open(&context) {
+            Some(x) => x,
+            None => {
+                println_error!("Submodule `{name}` is not initialized!");
+                println_hint!("  run `magoo install` to initialize the submodules");
+                return Err(GitError::NeedFix(false));
+            }
+        };
+
+        let (target_name, target_commit) = if self.upstream {
+            let commit = if self.fetch {
+                submodule.fetch_latest(&context)?
+            } else {
+                submodule.upstream_commit(&context)?
+            };
+            match commit {
+                Some(commit) => ("upstream", commit),
+                None => {
+                    println_error!("Cannot find the upstream commit of submodule `{name}`");
+                    println_hint!("  run with `--fetch` to fetch from the remote first");
+                    return Err(GitError::NeedFix(false));
+                }
+            }
+        } else {
+            match submodule.head_commit() {
+                Some(commit) => ("checked out", commit.to_string()),
+                None => {
+                    println_error!("Cannot find the checked out commit of submodule `{name}`");
+                    return Err(GitError::NeedFix(false));
+                }
+            }
+        };
+
+        let describe = |commit: &str| {
+            let short = &commit[..7];
+            match sub_context.describe(commit) {
+                Some(describe) => format!("{short} ({describe})"),
+                None => short.to_string(),
+            }
+        };
+        println_info!(
+            "\"{name}\": index at {}, {target_name} at {}",
+            describe(index_commit),
+            describe(&target_commit)
+        );
+        if index_commit == target_commit {
+            println_info!("No commits between index and {target_name}");
+            return Ok(());
+        }
+
+        let added = sub_context.log_oneline(&format!("{index_commit}..{target_commit}"))?;
+        let removed = sub_context.log_oneline(&format!("{target_commit}..{index_commit}"))?;
+        if !added.is_empty() {
+            println_info!();
+            println_info!(
+                "{} commit(s) in {target_name} but not in index:",
+                added.len()
+            );
+            for line in added {
+                println_info!("  + {line}");
+            }
+        }
+        if !removed.is_empty() {
+            println_info!();
+            println_warn!(
+                "{} commit(s) in index but not in {target_name}:",
+                removed.len()
+            );
+            for line in removed {
+                println_warn!("  - {line}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `changelog` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct ChangelogCommand {
+    /// The revision of the current repository to compare the index with
+    #[cfg_attr(feature = "cli", clap(long, default_value("HEAD")))]
+    pub since: String,
+
+    /// Write the changelog to a file instead of stdout
+    #[cfg_attr(feature = "cli", clap(long, short))]
+    pub output: Option<String>,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl ChangelogCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory and return the changelog in Markdown
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<String, GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let since = &self.since;
+        let old = Status::read_from_revision(&context, Some(since))?;
+        let new = Status::read_from_revision(&context, None)?;
+        let diffs = SubmoduleDiff::compute(&old, &new);
+        if diffs.is_empty() {
+            println_info!("No submodule changes since {since}");
+            println_hint!(
+                "  only changes staged in the index are included, run `git add ...` to stage them"
+            );
+            return Ok(String::new());
+        }
+
+        let status = Status::read_from(&context)?;
+        let mut changelog = format!("## Submodule changes since `{since}`\n");
+        for diff in &diffs {
+            changelog.push('\n');
+            Self::write_section(&context, &status, diff, &mut changelog)?;
+        }
+
+        match &self.output {
+            Some(output) => {
+                std::fs::write(output, &changelog)
+                    .map_err(|e| GitError::WriteFailed(output.to_string(), e))?;
+                println_info!("Changelog written to `{output}`");
+            }
+            None => {
+                print!("{changelog}");
+            }
+        }
+        Ok(changelog)
+    }
+
+    /// Write the Markdown section for one submodule
+    fn write_section(
+        context: &GitContext,
+        status: &Status,
+        diff: &SubmoduleDiff,
+        out: &mut String,
+    ) -> Result<(), GitError> {
+        let name = &diff.name;
+        let sub_context = status.find(name).and_then(|x| x.open(context));
+        let describe = |commit: &str| -> String {
+            match sub_context.as_ref().and_then(|x| x.describe_tags(commit)) {
+                Some(describe) => describe,
+                None => commit[..7].to_string(),
+            }
+        };
+
+        let old_commit = diff.old.as_ref().and_then(|x| x.index_commit());
+        let new_commit = diff.new.as_ref().and_then(|x| x.index_commit());
+        match (old_commit, new_commit) {
+            (None, Some(new_commit)) => {
+                out.push_str(&format!(
+                    "### `{name}` (added at {})\n",
+                    describe(new_commit)
+                ));
+            }
+            (Some(old_commit), None) => {
+                out.push_str(&format!(
+                    "### `{name}` (removed, was at {})\n",
+                    describe(old_commit)
+                ));
+            }
+            (Some(old_commit), Some(new_commit)) if old_commit != new_commit => {
+                out.push_str(&format!(
+                    "### `{name}` ({} -> {})\n",
+                    describe(old_commit),
+                    describe(new_commit)
+                ));
+            }
+            _ => {
+                out.push_str(&format!("### `{name}`\n"));
+            }
+        }
+
+        let mut notes = Vec::new();
+        if let Some((old_path, new_path)) = diff.moved() {
+            notes.push(format!("Moved from `{old_path}` to `{new_path}`"));
+        }
+        if let Some((old_url, new_url)) = diff.url_changed() {
+            notes.push(format!(
+                "URL changed from `{}` to `{}`",
+                old_url.unwrap_or("<none>"),
+                new_url.unwrap_or("<none>")
+            ));
+        }
+        if let Some((old_branch, new_branch)) = diff.branch_changed() {
+            notes.push(format!(
+                "Branch changed from `{}` to `{}`",
+                old_branch.unwrap_or("<none>"),
+                new_branch.unwrap_or("<none>")
+            ));
+        }
+        if !notes.is_empty() {
+            out.push('\n');
+            for note in notes {
+                out.push_str(&format!("- {note}\n"));
+            }
+        }
+
+        let ((old_commit, new_commit), sub_context) = match (diff.repointed(), &sub_context) {
+            (Some(commits), Some(sub_context)) => (commits, sub_context),
+            (Some(_), None) => {
+                out.push_str("\nCommits not available (submodule is not initialized)\n");
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        let logs = sub_context
+            .log_oneline(&format!("{old_commit}..{new_commit}"))
+            .and_then(|added| {
+                let removed = sub_context.log_oneline(&format!("{new_commit}..{old_commit}"))?;
+                Ok((added, removed))
+            });
+        let (added, removed) = match logs {
+            Ok(x) => x,
+            Err(e) => {
+                println_verbose!("Failed to read the commits of `{name}`: {e}");
+                out.push_str(
+                    "\nCommits not available (commits are not fetched in the submodule)\n",
+                );
+                return Ok(());
+            }
+        };
+        if !added.is_empty() {
+            out.push('\n');
+            for line in added {
+                out.push_str(&changelog_item(&line));
+            }
+        }
+        if !removed.is_empty() {
+            out.push_str("\nReverted:\n\n");
+            for line in removed {
+                out.push_str(&changelog_item(&line));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `export` command
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct ExportCommand {
+    /// Write the manifest to a file instead of stdout
+    #[cfg_attr(feature = "cli", clap(long, short))]
+    pub output: Option<String>,
+
+    /// Format of the manifest
+    ///
+    /// If not specified, TOML is used if the output file ends with `.toml`, and JSON otherwise.
+    #[cfg_attr(feature = "cli", clap(long, value_enum))]
+    pub format: Option<ManifestFormat>,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+#[cfg(feature = "serde")]
+impl ExportCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory and return the manifest
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<Manifest, GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let status = Status::read_from(&context)?;
+        let manifest = Manifest::from_status(&status);
+        let format = match (self.format, &self.output) {
+            (Some(format), _) => format,
+            (None, Some(output)) => ManifestFormat::from_path(output),
+            (None, None) => ManifestFormat::Json,
+        };
+        let content = manifest.to_string(format)?;
+
+        match &self.output {
+            Some(output) => {
+                std::fs::write(output, &content)
+                    .map_err(|e| GitError::WriteFailed(output.to_string(), e))?;
+                println_info!(
+                    "Exported {} submodule(s) to `{output}`",
+                    manifest.submodules.len()
+                );
+            }
+            None => {
+                print!("{content}");
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+/// The `import` command
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct ImportCommand {
+    /// The manifest file to import
+    ///
+    /// The file is read as TOML if it ends with `.toml`, and JSON otherwise.
+    pub file: String,
+
+    /// Whether to force the submodules to be added and pinned
+    ///
+    /// This will pass the `--force` flag to `git submodule add` and `git submodule update`, and
+    /// pin submodules with uncommitted changes (the changes may be lost).
+    #[cfg_attr(feature = "cli", clap(long, short))]
+    pub force: bool,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+#[cfg(feature = "serde")]
+impl ImportCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<(), GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let file = &self.file;
+        let content =
+            std::fs::read_to_string(file).map_err(|e| GitError::ReadFailed(file.to_string(), e))?;
+        let manifest = Manifest::parse(&content, ManifestFormat::from_path(file))?;
+
+        let mut status = Status::read_from(&context)?;
+        for submodule in status.flattened_mut() {
+            submodule.fix(&context, false)?;
+        }
+
+        // check for conflicts before changing anything
+        let mut has_conflict = false;
+        for entry in &manifest.submodules {
+            let name = &entry.name;
+            if let Some(submodule) = status.modules.get(name) {
+                if submodule.path() != Some(&entry.path)
+                    || submodule.versioned_url() != Some(&entry.url)
+                {
+                    println_error!(
+                        "Submodule `{name}` already exists with a different path or URL!"
+                    );
+                    has_conflict = true;
+                } else if !self.force && is_dirty(&context, submodule)? {
+                    has_conflict = true;
+                }
+            } else if let Some(submodule) = status.find(&entry.path) {
+                println_error!(
+                    "Path `{}` of submodule `{name}` is used by submodule `{}`!",
+                    entry.path,
+                    submodule.name().unwrap_or("<unknown>")
+                );
+                has_conflict = true;
+            }
+        }
+        if has_conflict {
+            println_hint!("  remove or fix the conflicting submodules and try again");
+            return Err(GitError::NeedFix(false));
+        }
+
+        let top_level_dir = context.top_level_dir()?;
+        for entry in &manifest.submodules {
+            let name = &entry.name;
+            if status.modules.contains_key(name) {
+                println_verbose!("Submodule `{name}` already exists");
+                continue;
+            }
+            println_info!("Adding submodule `{name}` from url: {}", entry.url);
+            context.submodule_add(
+                &entry.url,
+                Some(&entry.path),
+                entry.branch.as_deref(),
+                Some(name),
+                None,
+                self.force,
+            )?;
+            if entry.shallow {
+                context.set_config(
+                    top_level_dir.join(".gitmodules"),
+                    &format!("submodule.{name}.shallow"),
+                    Some("true"),
+                )?;
+                context.add(".gitmodules")?;
+            }
+            if let Some(version) = &entry.version {
+                context.set_config(
+                    top_level_dir.join(".gitmodules"),
+                    &format!("submodule.{name}.magoo-version"),
+                    Some(version),
+                )?;
+                context.add(".gitmodules")?;
+            }
+        }
+
+        let status = Status::read_from(&context)?;
+        for entry in &manifest.submodules {
+            let submodule = status
+                .modules
+                .get(&entry.name)
+                .ok_or_else(|| GitError::ModuleNotFound(entry.name.to_string()))?;
+            if submodule.open(&context).is_none() {
+                context.submodule_init(Some(&entry.path))?;
+                context.submodule_update(&[&entry.path], self.force, false, true, None)?;
+            }
+        }
+
+        let status = Status::read_from(&context)?;
+        for entry in &manifest.submodules {
+            let name = &entry.name;
+            let submodule = status
+                .modules
+                .get(name)
+                .ok_or_else(|| GitError::ModuleNotFound(name.to_string()))?;
+            let commit = Some(entry.commit.as_str());
+            if submodule.index_commit() != commit || submodule.head_commit() != commit {
+                println_verbose!("Pinning submodule `{name}` to {}", entry.commit);
+                submodule.checkout(&context, &entry.commit)?;
+                context.add(&entry.path)?;
+            }
+        }
+
+        println_info!();
+        println_info!(
+            "Imported {} submodule(s) successfully.",
+            manifest.submodules.len()
+        );
+        println_hint!("  run `git status` to check the changes and commit them");
+        println_hint!("  run `magoo status` to check the status of the submodules");
+        Ok(())
+    }
+}
+
+/// The `move` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct MoveCommand {
+    /// The name of the submodule to move
+    pub name: String,
+
+    /// The new path of the submodule
+    ///
+    /// The path should be relative from the top level (root) of the git repository.
+    pub path: String,
+
+    /// Bypass warnings in the submodule state
+    #[cfg_attr(feature = "cli", clap(long))]
+    pub bypass: bool,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl MoveCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<(), GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let name = &self.name;
+        let new_path = match submodule::normalize_repo_path(&self.path) {
+            Some(x) => x,
+            None => {
+                println_error!("Path `{}` is not inside the repository!", self.path);
+                println_hint!("  the path should be relative from the top level of the repository");
+                return Err(GitError::NeedFix(false));
+            }
+        };
+        let new_path = new_path.as_str();
+        let mut status = Status::read_from(&context)?;
+        if let Some(other) = status.find(new_path) {
+            println_error!(
+                "Path `{new_path}` is already used by submodule `{}`!",
+                other.name().or(other.path()).unwrap_or("<unknown>")
+            );
+            return Err(GitError::NeedFix(false));
+        }
+        if context.top_level_dir()?.join(new_path).exists() {
+            println_error!("Path `{new_path}` already exists!");
+            return Err(GitError::NeedFix(false));
+        }
+
+        let submodule = &*find_submodule(&mut status, name, "move")?;
+        check_healthy(&context, submodule, name, self.bypass)?;
+
+        let old_path = submodule.path().unwrap_or_default().to_string();
+        submodule.move_to(&context, new_path)?;
+
+        let status = Status::read_from(&context)?;
+        let consistent = match status.modules.get(name) {
+            Some(submodule) => submodule.resolved_paths(&context)?.is_consistent(),
+            None => false,
+        };
+        if !consistent {
+            println_error!("Submodule `{name}` is not consistent after moving!");
+            println_hint!("  run `magoo status` to investigate.");
+            return Err(GitError::NeedFix(false));
+        }
+
+        println_info!("Moved submodule `{name}` from `{old_path}` to `{new_path}`");
+        println_hint!("  run `git status` to check the changes and commit them");
+        Ok(())
+    }
+}
+
+/// The `rename` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct RenameCommand {
+    /// The current name of the submodule
+    pub name: String,
+
+    /// The new name of the submodule
+    pub new_name: String,
+
+    /// Bypass warnings in the submodule state
+    #[cfg_attr(feature = "cli", clap(long))]
+    pub bypass: bool,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl RenameCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<(), GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let name = &self.name;
+        let new_name = self.new_name.trim_matches('/');
+        if new_name.is_empty() || new_name.split('/').any(|x| x.is_empty() || x == "..") {
+            println_error!("`{}` is not a valid submodule name!", self.new_name);
+            return Err(GitError::NeedFix(false));
+        }
+        let mut status = Status::read_from(&context)?;
+        if status.modules.contains_key(new_name) {
+            println_error!("Submodule `{new_name}` already exists!");
+            return Err(GitError::NeedFix(false));
+        }
+
+        if new_name.starts_with(&format!("{name}/")) || name.starts_with(&format!("{new_name}/")) {
+            println_error!("Cannot rename submodule `{name}` to `{new_name}`!");
+            println_hint!(
+                "  the module directories in .git/modules cannot be nested in each other, rename to a temporary name first"
+            );
+            return Err(GitError::NeedFix(false));
+        }
+
+        let submodule = &*find_submodule(&mut status, name, "rename")?;
+        check_healthy(&context, submodule, name, self.bypass)?;
+
+        submodule.rename(&context, new_name)?;
+
+        println_info!("Renamed submodule `{name}` to `{new_name}`");
+        println_hint!("  run `git status` to check the changes and commit them");
+        Ok(())
+    }
+}
+
+/// The `foreach` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct ForeachCommand {
+    /// Number of submodules to run the command in at the same time
+    #[cfg_attr(feature = "cli", clap(long, short, default_value("1")))]
+    pub parallel: usize,
+
+    /// Also run the command in nested submodules
+    #[cfg_attr(feature = "cli", clap(long, short))]
+    pub recursive: bool,
+
+    /// Run the command in unhealthy submodules as well
+    ///
+    /// By default, unhealthy submodules are skipped.
+    #[cfg_attr(feature = "cli", clap(long))]
+    pub bypass: bool,
+
+    /// The command to run
+    ///
+    /// Use `$sm_path` for the path of the submodule. `$path` is not set on Windows.
+    #[cfg_attr(feature = "cli", clap(last(true), required(true)))]
+    pub command: Vec<String>,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl ForeachCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<(), GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+
+        let tree = {
+            let _guard = context.lock()?;
+            if self.recursive {
+                Status::read_recursive(&context)?
+            } else {
+                StatusTree::from(Status::read_from(&context)?)
+            }
+        };
+
+        let top_level_dir = context.top_level_dir()?;
+        let mut jobs = Vec::new();
+        let mut skipped = 0;
+        tree.walk(&context, &mut |sub_context, submodule, depth| {
+            let name = submodule.name().or(submodule.path()).unwrap_or("<unknown>");
+            let mut display_name = name.to_string();
+            let sub_top_level_dir = sub_context.top_level_dir()?;
+            if depth > 0
+                && let Some(parent) = pathdiff::diff_paths(sub_top_level_dir, top_level_dir)
+            {
+                display_name = format!("{name} (in {})", parent.to_cmd_arg());
+            }
+            let (path, sha) = match (submodule.path(), submodule.head_commit()) {
+                (Some(path), Some(sha)) => (path, sha),
+                _ => {
+                    println_verbose!("Skipping `{display_name}` that is not initialized");
+                    return Ok(());
+                }
+            };
+            if !self.bypass && !submodule.is_healthy(sub_context)? {
+                println_warn!("Skipping unhealthy submodule `{display_name}`");
+                skipped += 1;
+                return Ok(());
+            }
+            jobs.push(ForeachJob {
+                display_name,
+                name: name.to_string(),
+                path: path.to_string(),
+                sha: sha.to_string(),
+                toplevel: sub_top_level_dir.to_cmd_arg(),
+                dir: sub_top_level_dir.join(path),
+            });
+            Ok(())
+        })?;
+
+        let command = match self.command.as_slice() {
+            [command] => command.to_string(),
+            args => args
+                .iter()
+                .map(|x| quote_arg(x))
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        println_verbose!("Running `{command}` in {} submodule(s)", jobs.len());
+        let results = foreach::run_parallel(&jobs, &command, self.parallel, |job, output| {
+            println_info!("Entering `{}`", job.display_name);
+            match output {
+                Ok(output) => {
+                    print_info!("{}", String::from_utf8_lossy(&output.stdout));
+                    eprint_info!("{}", String::from_utf8_lossy(&output.stderr));
+                    if !output.status.success() {
+                        println_error!("`{}` failed: {}", job.display_name, output.status);
+                    }
+                }
+                Err(e) => {
+                    println_error!("`{}` failed: {e}", job.display_name);
+                }
+            }
+        });
+
+        let failed = jobs
+            .iter()
+            .zip(&results)
+            .filter(|(_, success)| !**success)
+            .map(|(job, _)| job.display_name.as_str())
+            .collect::<Vec<_>>();
+        println_info!();
+        println_info!(
+            "{} succeeded, {} failed, {skipped} skipped",
+            jobs.len() - failed.len(),
+            failed.len()
+        );
+        if !failed.is_empty() {
+            println_error!("The command failed in: {}", failed.join(", "));
+            return Err(GitError::NeedFix(false));
+        }
+        if skipped > 0 {
+            println_hint!("  use the `--bypass` flag to run the command in unhealthy submodules");
+        }
+        Ok(())
+    }
+}
+
+/// The `pin` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct PinCommand {
+    /// The name of the submodule to pin
+    pub name: String,
+
+    /// The commit, tag or branch to pin the submodule to
+    ///
+    /// Branches are fetched from the remote before being resolved, so `main` pins to the
+    /// latest commit on the remote. Full commit hashes and tags are only fetched if they are
+    /// not found locally.
+    pub rev: String,
+
+    /// Pin the submodule even if it has uncommitted changes (the changes may be lost)
+    #[cfg_attr(feature = "cli", clap(long, short))]
+    pub force: bool,
+
+    /// Bypass warnings in the submodule state
+    #[cfg_attr(feature = "cli", clap(long))]
+    pub bypass: bool,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl PinCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory and return the new commit
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<String, GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let name = &self.name;
+        let mut status = Status::read_from(&context)?;
+        let submodule = &*find_submodule(&mut status, name, "pin")?;
+        check_healthy(&context, submodule, name, self.bypass)?;
+        let sub_context = match submodule.open(&context) {
+            Some(x) => x,
+            None => {
+                println_error!("Submodule `{name}` is not initialized!");
+                println_hint!("  run `magoo install` to initialize the submodules first");
+                return Err(GitError::NeedFix(false));
+            }
+        };
+        if !self.force && is_dirty(&context, submodule)? {
+            return Err(GitError::NeedFix(false));
+        }
+
+        let rev = &self.rev;
+        let old_commit = submodule
+            .head_commit()
+            .or(submodule.index_commit())
+            .map(|x| x.to_string());
+        submodule.checkout(&context, rev)?;
+        if let Some(path) = submodule.path() {
+            context.add(path)?;
+        }
+        let new_commit = match sub_context.head()? {
+            Some(x) => x,
+            None => return Err(GitError::RevisionNotFound(rev.to_string())),
+        };
+
+        let describe = |commit: &str| match sub_context.describe_tags(commit) {
+            Some(describe) => describe,
+            None => commit[..7].to_string(),
+        };
+        match old_commit {
+            Some(old_commit) if old_commit == new_commit => {
+                println_info!("Submodule `{name}` is already at {}", describe(&new_commit));
+                return Ok(new_commit);
+            }
+            Some(old_commit) => {
+                println_info!(
+                    "Pinned submodule `{name}`: {} -> {}",
+                    describe(&old_commit),
+                    describe(&new_commit)
+                );
+            }
+            None => {
+                println_info!("Pinned submodule `{name}` to {}", describe(&new_commit));
+            }
+        }
+        println_hint!("  the change is staged, run `git commit` to commit it");
+        Ok(new_commit)
+    }
+}
+
+/// The `vendor` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct VendorCommand {
+    /// The name of the submodule to vendor
+    pub name: String,
+
+    /// Commit the change, with the source URL and commit recorded as trailers in the message
+    ///
+    /// Only the changes for vendoring the submodule are committed, other staged changes are
+    /// not allowed.
+    #[cfg_attr(feature = "cli", clap(long))]
+    pub commit: bool,
+
+    /// Vendor the submodule even if it has uncommitted changes
+    ///
+    /// The uncommitted changes are included in the vendored files.
+    #[cfg_attr(feature = "cli", clap(long, short))]
+    pub force: bool,
+
+    /// Bypass warnings in the submodule state
+    #[cfg_attr(feature = "cli", clap(long))]
+    pub bypass: bool,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl VendorCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<(), GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let name = &self.name;
+        let mut status = Status::read_from(&context)?;
+        let submodule = find_submodule(&mut status, name, "vendor")?;
+        check_healthy(&context, submodule, name, self.bypass)?;
+        let (sub_context, commit) = match (submodule.open(&context), submodule.head_commit()) {
+            (Some(sub_context), Some(commit)) => (sub_context, commit.to_string()),
+            _ => {
+                println_error!("Submodule `{name}` is not initialized!");
+                println_hint!("  run `magoo install` to initialize the submodules first");
+                return Err(GitError::NeedFix(false));
+            }
+        };
+        if !Status::read_from(&sub_context)?.flattened().is_empty() {
+            println_error!("Submodule `{name}` has nested submodules!");
+            println_hint!(
+                "  vendor or remove the nested submodules inside `{name}` first, then try again"
+            );
+            return Err(GitError::NeedFix(false));
+        }
+        if !self.force && is_dirty(&context, submodule)? {
+            return Err(GitError::NeedFix(false));
+        }
+        if self.commit && context.has_staged_changes()? {
+            println_error!("There are staged changes in the repository!");
+            println_hint!("  commit or unstage the changes first, or vendor without `--commit`");
+            return Err(GitError::NeedFix(false));
+        }
+
+        let url = submodule.url().unwrap_or_default().to_string();
+        submodule.vendor(&context)?;
+
+        let message = format!(
+            "Vendor {name} at {}\n\nVendored-From: {url}\nVendored-Commit: {commit}",
+            &commit[..7]
+        );
+        println_info!();
+        if self.commit {
+            context.commit(&message)?;
+            println_info!("Submodule `{name}` vendored and committed.");
+        } else {
+            println_info!("Submodule `{name}` vendored successfully.");
+            println_hint!("  run `git status` to check the changes");
+            println_hint!("  consider recording the source in the commit message:");
+            println_hint!("    Vendored-From: {url}");
+            println_hint!("    Vendored-Commit: {commit}");
+        }
+        Ok(())
+    }
+}
+
+/// The `extract` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct ExtractCommand {
+    /// The directory to extract, relative from the top level (root) of the git repository
+    pub path: String,
+
+    /// URL of the repository to push the history of the directory to
+    ///
+    /// If the URL is a local path that doesn't exist, a bare repository is created there.
+    #[cfg_attr(feature = "cli", clap(long, short))]
+    pub url: String,
+
+    /// Branch to push to in the new repository, which the submodule also tracks
+    #[cfg_attr(feature = "cli", clap(long, short, default_value("main")))]
+    pub branch: String,
+
+    /// Name of the submodule
+    ///
+    /// If not specified, the name of the submodule is the same as the path.
+    #[cfg_attr(feature = "cli", clap(long))]
+    pub name: Option<String>,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl ExtractCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<(), GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let path = self.path.trim_end_matches(['/', '\\']);
+        let status = Status::read_from(&context)?;
+        if let Some(submodule) = status.find(path) {
+            println_error!(
+                "`{path}` is already a submodule `{}`!",
+                submodule.name().unwrap_or("<unknown>")
+            );
+            return Err(GitError::NeedFix(false));
+        }
+        if context.ls_files(&["--", path])?.is_empty() {
+            println_error!("`{path}` does not contain any tracked files!");
+            return Err(GitError::NeedFix(false));
+        }
+        if !context.status_porcelain_ignored(path)?.is_empty() {
+            println_error!("`{path}` has uncommitted, untracked or ignored files!");
+            println_hint!("  commit or clean the changes first, the directory will be replaced");
+            return Err(GitError::NeedFix(false));
+        }
+
+        // local paths are made absolute so they are not treated as relative to the remote
+        let mut url = self.url.clone();
+        let is_local = is_local_url(&url);
+        if is_local {
+            let local_path = std::path::absolute(Path::new(dir).join(&url))
+                .map_err(|e| GitError::CanonicalizeFail(url.clone(), e))?;
+            if !local_path.exists() {
+                println_info!("Creating bare repository at `{}`", local_path.to_cmd_arg());
+                context.init_bare(&local_path.to_cmd_arg())?;
+            }
+            url = local_path.canonicalize_git()?.to_cmd_arg();
+        }
+
+        println_info!("Splitting the history of `{path}`");
+        let commit = context.subtree_split(path)?;
+        let branch = &self.branch;
+        println_info!("Pushing {} to `{branch}` of `{url}`", &commit[..7]);
+        context.push(&url, &format!("{commit}:refs/heads/{branch}"))?;
+
+        println_info!("Replacing `{path}` with the submodule");
+        context.remove_recursive(path)?;
+        let top_level_dir = context.top_level_dir()?;
+        let _ = std::fs::remove_dir_all(top_level_dir.join(path));
+        // cloning from a local path is not allowed by default since git 2.38.1
+        let result = if is_local {
+            context.submodule_add_local(&url, Some(path), Some(branch), self.name.as_deref(), false)
+        } else {
+            context.submodule_add(
+                &url,
+                Some(path),
+                Some(branch),
+                self.name.as_deref(),
+                None,
+                false,
+            )
+        };
+        if let Err(e) = result {
+            println_error!("Failed to add the submodule: {e}");
+            println_hint!("  `{path}` is restored, the history was already pushed to `{url}`");
+            let _ = std::fs::remove_dir_all(top_level_dir.join(path));
+            let modules_dir = context.git_dir()?.join("modules");
+            let module_dir = modules_dir.join(self.name.as_deref().unwrap_or(path));
+            if module_dir.exists() {
+                let _ = std::fs::remove_dir_all(&module_dir);
+            }
+            submodule::remove_empty_parents(&module_dir, &modules_dir);
+            context.restore_from_head(path)?;
+            return Err(GitError::NeedFix(false));
+        }
+
+        println_info!();
+        println_info!("Extracted `{path}` to `{url}` at {}", &commit[..7]);
+        println_hint!("  run `git status` to check the changes and commit them");
+        Ok(())
+    }
+}
+
+/// The `adopt` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct AdoptCommand {
+    /// Paths of the repositories to register as submodules, relative from the top level (root)
+    /// of the git repository
+    pub paths: Vec<String>,
+
+    /// Register all repositories found
+    #[cfg_attr(feature = "cli", clap(long, conflicts_with("paths")))]
+    pub all: bool,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl AdoptCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<(), GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let status = Status::read_from(&context)?;
+        let repos = status.find_embedded_repos(&context)?;
+        if self.paths.is_empty() && !self.all {
+            if repos.is_empty() {
+                println_info!("No unregistered git repositories found");
+                return Ok(());
+            }
+            println_info!("Found git repositories that are not submodules:");
+            for repo in &repos {
+                let url = repo.url.as_deref().unwrap_or("<no remote>");
+                match &repo.branch {
+                    Some(branch) => println_info!("  {} from {url} ({branch})", repo.path),
+                    None => println_info!("  {} from {url}", repo.path),
+                }
+            }
+            println_hint!("  run `magoo adopt PATH...` or `magoo adopt --all` to register them");
+            return Ok(());
+        }
+
+        let mut selected = Vec::new();
+        for path in &self.paths {
+            let path = path.trim_end_matches(['/', '\\']);
+            match repos.iter().find(|x| x.path == path) {
+                Some(repo) => selected.push(repo),
+                None => {
+                    println_error!("`{path}` is not an unregistered git repository!");
+                    println_hint!("  run `magoo adopt` to list the repositories found");
+                    return Err(GitError::NeedFix(false));
+                }
+            }
+        }
+        if self.all {
+            selected.extend(&repos);
+        }
+
+        let mut adopted = 0;
+        for repo in selected {
+            let path = &repo.path;
+            let url = match &repo.url {
+                Some(url) => url,
+                None => {
+                    println_warn!("Skipping `{path}` that doesn't have a remote URL");
+                    continue;
+                }
+            };
+            println_info!("Adopting `{path}` from {url}");
+            if repo.in_index {
+                context.remove_cached(path)?;
+            }
+            context.submodule_add(url, Some(path), repo.branch.as_deref(), None, None, false)?;
+            context.submodule_absorbgitdirs(Some(path))?;
+            adopted += 1;
+        }
+
+        println_info!();
+        println_info!("Adopted {adopted} repository(s) as submodules.");
+        println_hint!("  run `git status` to check the changes and commit them");
+        Ok(())
+    }
+}
+
+/// The `absorb` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct AbsorbCommand {
+    /// Name of the submodule to absorb
+    ///
+    /// If not specified, all submodules with embedded git directories are absorbed.
+    pub name: Option<String>,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl AbsorbCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<(), GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let mut status = Status::read_from(&context)?;
+        let mut absorbed = 0;
+        let mut unregistered = Vec::new();
+        match &self.name {
+            Some(name) => {
+                let submodule = find_submodule(&mut status, name, "absorb")?;
+                if submodule.absorb_git_dir(&context)? {
+                    absorbed += 1;
+                }
+            }
+            None => {
+                for submodule in status.flattened_mut() {
+                    if submodule.absorb_git_dir(&context)? {
+                        absorbed += 1;
+                    } else if submodule.is_unregistered_repo(&context)?
+                        && let Some(path) = submodule.path()
+                    {
+                        unregistered.push(path.to_string());
+                    }
+                }
+            }
+        }
+
+        if !unregistered.is_empty() {
+            println_warn!("Skipped git repositories that are not in .gitmodules:");
+            for path in &unregistered {
+                println_warn!("  {path}");
+            }
+            println_hint!("  run `magoo adopt PATH...` to register them as submodules");
+        }
+        if absorbed == 0 {
+            println_info!("No embedded git directories found");
+        } else {
+            println_info!();
+            println_info!("Absorbed {absorbed} git directory(s) into .git/modules.");
+            println_hint!("  run `magoo status` to check the status of the submodules");
+        }
+        Ok(())
+    }
+}
+
+/// The `commit` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct CommitCommand {
+    /// Names of the submodules to commit
+    ///
+    /// If not specified, all submodules with a different commit checked out are committed.
+    pub names: Vec<String>,
+
+    /// Subject line of the commit message, instead of the generated one
+    ///
+    /// The list of submodules is still added to the body of the message.
+    #[cfg_attr(feature = "cli", clap(long, short))]
+    pub message: Option<String>,
+
+    /// Print the commit message without staging or committing anything
+    #[cfg_attr(feature = "cli", clap(long))]
+    pub dry_run: bool,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl CommitCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory and return the commit message
+    ///
+    /// Only the submodules are committed. Other changes staged in the index are left as is.
+    /// Returns [`None`] if there is nothing to commit
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<Option<String>, GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let mut status = Status::read_from(&context)?;
+        for name in &self.names {
+            find_submodule(&mut status, name, "commit")?;
+        }
+
+        let mut paths = Vec::new();
+        let mut lines = Vec::new();
+        for submodule in status.flattened() {
+            let name = submodule.name().unwrap_or_default();
+            if !self.names.is_empty() && !self.names.iter().any(|x| x == name) {
+                continue;
+            }
+            let (path, old, new) = match (
+                submodule.path(),
+                submodule.index_commit(),
+                submodule.head_commit(),
+            ) {
+                (Some(path), Some(old), Some(new)) if old != new => (path, old, new),
+                _ => continue,
+            };
+            let sub_context = match submodule.open(&context) {
+                Some(sub_context) => sub_context,
+                None => continue,
+            };
+            let display_name = submodule.name().unwrap_or(path);
+            let describe = |commit: &str| {
+                sub_context
+                    .describe_tags(commit)
+                    .unwrap_or_else(|| commit[..7].to_string())
+            };
+            let new_describe = describe(new);
+            let mut line = format!("{display_name}: {} -> {new_describe}", describe(old));
+            // the old commit might not exist in a shallow clone
+            if let Ok((removed, added)) = sub_context.count_left_right(old, new) {
+                line.push_str(&format!(" ({})", describe_commit_count(added, removed)));
+            }
+            println_verbose!("Committing submodule `{display_name}` at `{path}`");
+            paths.push(path);
+            lines.push((display_name, new_describe, line));
+        }
+
+        if paths.is_empty() {
+            println_info!("No submodule changes to commit");
+            return Ok(None);
+        }
+
+        let subject = match (&self.message, lines.as_slice()) {
+            (Some(message), _) => message.clone(),
+            (None, [(name, new, _)]) => format!("Update {name} to {new}"),
+            (None, _) => format!("Update {} submodules", lines.len()),
+        };
+        let body = lines
+            .iter()
+            .map(|(_, _, line)| line.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let message = format!("{subject}\n\n{body}");
+
+        if self.dry_run {
+            println_info!("{message}");
+            return Ok(Some(message));
+        }
+        for path in &paths {
+            context.add(path)?;
+        }
+        context.commit_paths(&message, &paths)?;
+
+        println_info!("{message}");
+        println_info!();
+        println_info!("Committed {} submodule(s).", paths.len());
+        Ok(Some(message))
+    }
+}
+
+/// The `doctor` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct DoctorCommand {
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+impl DoctorCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory
+    ///
+    /// The git version is reported instead of checked, and the repository is not locked, so
+    /// this works when other commands don't.
+    pub fn run(&self, dir: &str, _common: &OtherOptions) -> Result<(), GitError> {
+        println_info!("magoo version: {}", env!("CARGO_PKG_VERSION"));
+        match which::which("git") {
+            Ok(path) => println_info!("git: {}", path.to_cmd_arg()),
+            Err(_) => {
+                println_error!("git: not found");
+                println_hint!("  install git and make sure it is in PATH");
+                return Err(GitError::NotInstalled);
+            }
+        }
+        let context = GitContext::try_from(dir)?;
+        match context.git_version() {
+            Ok(version) if version::is_supported(&version) => {
+                println_info!("git version: {version} (supported)");
+            }
+            Ok(version) => {
+                println_warn!(
+                    "git version: {version} (not supported, supported versions are: {})",
+                    version::get_supported_versions()
+                );
+                println_hint!("  other commands need `--allow-unsupported` with this version");
+            }
+            Err(e) => println_warn!("git version: {e}"),
+        }
+
+        let git_dir = match context.git_dir() {
+            Ok(git_dir) => git_dir,
+            Err(e) => {
+                println_error!("git dir: not a git repository ({e})");
+                println_hint!(
+                    "  run magoo inside a git repository, or use `magoo -C <dir> doctor`"
+                );
+                return Err(GitError::NeedFix(false));
+            }
+        };
+        println_info!("git dir: {}", git_dir.to_cmd_arg());
+        let top_level_dir = context.top_level_dir()?;
+        println_info!("top level dir: {}", top_level_dir.to_cmd_arg());
+
+        let lock_path = git_dir.join("magoo.lock");
+        if !lock_path.exists() {
+            println_info!("lock file: none");
+        } else if Guard::is_held(&lock_path) {
+            println_warn!("lock file: held by another magoo process");
+        } else {
+            println_warn!("lock file: stale");
+            println_hint!(
+                "  no magoo process is holding it, remove `{}` so other commands don't wait on it",
+                lock_path.to_cmd_arg()
+            );
+        }
+
+        let status = Status::read_from(&context)?;
+        let submodules = status.flattened();
+        let file_allow = context.get_effective_config("protocol.file.allow");
+        println_info!(
+            "protocol.file.allow: {}",
+            file_allow.as_deref().unwrap_or("<not set>")
+        );
+        if file_allow.as_deref() != Some("always")
+            && submodules.iter().any(|x| {
+                x.url()
+                    .is_some_and(|url| is_local_url(url) && !url.starts_with('.'))
+            })
+        {
+            println_hint!(
+                "  cloning submodules from local paths fails unless this is `always` (git 2.38.1+)"
+            );
+        }
+        let recurse = context.get_effective_config("submodule.recurse");
+        println_info!(
+            "submodule.recurse: {}",
+            recurse.as_deref().unwrap_or("<not set>")
+        );
+        if recurse.as_deref() == Some("true") {
+            println_hint!("  git commands like `checkout` and `pull` also update the submodules");
+        }
+
+        let remote = context.default_remote()?;
+        let base_url = match context.remote_url(&remote) {
+            Some(url) => {
+                println_info!("remote: {remote} ({url})");
+                url
+            }
+            None => {
+                println_info!("remote: <none>, relative URLs are resolved from the top level dir");
+                top_level_dir.to_cmd_arg()
+            }
+        };
+
+        println_info!("submodules: {}", submodules.len());
+        let mut has_issues = false;
+        for submodule in &submodules {
+            let name = submodule.name().or(submodule.path()).unwrap_or("<unknown>");
+            println_info!("  {name}:");
+            let gitmodules_url = submodule
+                .in_gitmodules
+                .as_ref()
+                .and_then(|x| x.url.as_deref());
+            if let Some(url) = gitmodules_url
+                && let Some(resolved) = git::resolve_relative_url(&base_url, url)
+            {
+                println_info!("    url: {url} -> {resolved}");
+                if let Some(config) = &submodule.in_config
+                    && config.url != resolved
+                {
+                    println_warn!("    ! .git/config has a different URL: {}", config.url);
+                    println_hint!("      run `git submodule sync` to update it");
+                }
+            }
+            let failures = submodule.check(&context)?;
+            if failures.is_empty() {
+                println_info!("    healthy");
+            }
+            for failure in failures {
+                println_warn!("    ! {}", failure.describe());
+                if let CheckFailure::Unhealthy(issue) = failure {
+                    has_issues = true;
+                    println_hint!("      {}", issue.explain());
+                }
+            }
+            if submodule.has_embedded_git_dir(&context)? {
+                println_warn!("    ! has an embedded git directory");
+                println_hint!("      run `magoo absorb` to move it into .git/modules");
+            }
+        }
+        if has_issues {
+            println_hint!("run `magoo status --fix` to fix the issues");
+        }
+        Ok(())
+    }
+}
+
+/// The `trash` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct TrashCommand {
+    /// What to do with the trash. Lists the entries if not specified
+    #[cfg_attr(feature = "cli", clap(subcommand))]
+    pub action: Option<TrashAction>,
+
+    /// Print options
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub options: PrintOptions,
+}
+
+/// Subcommands of the `trash` command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Subcommand))]
+pub enum TrashAction {
+    /// List the entries in the trash, with the directories in each entry
+    List,
+    /// Move the directories in an entry back to where they were removed from
+    ///
+    /// Only the directories are restored. The submodule configuration and the index are not
+    /// changed.
+    Restore {
+        /// ID of the entry, as shown by `magoo trash list`
+        id: String,
+    },
+    /// Permanently delete everything in the trash
+    Empty,
+}
+
+impl TrashCommand {
+    /// Apply the print options
+    pub fn set_print_options(&self) {
+        self.options.apply();
+    }
+
+    /// Run the command in the given directory
+    pub fn run(&self, dir: &str, common: &OtherOptions) -> Result<(), GitError> {
+        let context = GitContext::try_from(dir)?;
+        if !common.allow_unsupported {
+            context.check_version(false)?;
+        }
+        let _guard = context.lock()?;
+
+        let trash = Trash::open(&context)?;
+        match self.action.as_ref().unwrap_or(&TrashAction::List) {
+            TrashAction::List => {
+                let entries = trash.list()?;
+                if entries.is_empty() {
+                    println_info!("The trash is empty");
+                    return Ok(());
+                }
+                for entry in entries {
+                    println_info!("{}: {}", entry.id, entry.command);
+                    for item in entry.items {
+                        println_info!("    {}", item.path);
+                    }
+                }
+                println_hint!("  run `magoo trash restore <id>` to move the directories back");
+            }
+            TrashAction::Restore { id } => {
+                let entry = match trash.restore(id) {
+                    Ok(entry) => entry,
+                    Err(GitError::TrashNotFound(_)) => {
+                        println_error!("Trash entry `{id}` not found!");
+                        println_hint!("  run `magoo trash list` to see the entries");
+                        return Err(GitError::NeedFix(false));
+                    }
+                    Err(e) => return Err(e),
+                };
+                for item in &entry.items {
+                    println_info!("Restored `{}`", item.path);
+                }
+                println_info!();
+                println_info!("Trash entry `{id}` restored.");
+                println_hint!("  run `magoo status` to check the status of the submodules");
+                println_hint!(
+                    "  the submodules might need to be added back with `magoo adopt` or `magoo install`"
+                );
+            }
+            TrashAction::Empty => {
+                let count = trash.empty()?;
+                println_info!("Deleted {count} trash entry(s)");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Describe the number of commits between the old and new commit of a submodule
+fn describe_commit_count(added: usize, removed: usize) -> String {
+    let plural = |n: usize| if n == 1 { "" } else { "s" };
+    match (added, removed) {
+        (added, 0) => format!("{added} commit{}", plural(added)),
+        (0, removed) => format!("{removed} commit{} reverted", plural(removed)),
+        (added, removed) => format!("{added} commit{}, {removed} reverted", plural(added)),
+    }
+}
+
+/// Print the header of the plan printed with `--dry-run`
+fn print_dry_run_header(context: &GitContext) -> Result<(), GitError> {
+    let top_level_dir = context.top_level_dir()?;
+    println_info!(
+        "Dry run, nothing will be changed. Planned actions (git runs in `{}`):",
+        top_level_dir.to_cmd_arg()
+    );
+    Ok(())
+}
+
+/// Print the planned actions of one submodule
+fn print_submodule_plan(name: &str, actions: &[Action]) {
+    if actions.is_empty() {
+        println_info!("{name}: nothing to do");
+        return;
+    }
+    println_info!("{name}:");
+    plan::print_actions(actions, "  ");
+}
+
+/// Check if the URL is a local path instead of a URL like `https://...` or `git@host:path`
+fn is_local_url(url: &str) -> bool {
+    if url.contains("://") {
+        return false;
+    }
+    match url.split_once(':') {
+        // drive letter on Windows, like `C:\...`
+        Some((host, _)) if host.len() == 1 => true,
+        Some((host, _)) => host.contains(['/', '\\']),
+        None => true,
+    }
+}
+
+/// Update the submodules that track a semver requirement to the highest matching tag. If
+/// `name` is specified, only that submodule is updated
+fn update_to_versions(context: &GitContext, name: Option<&str>) -> Result<(), GitError> {
+    let status = Status::read_from(context)?;
+    for submodule in status.flattened() {
+        let (submodule_name, version_req) = match (submodule.name(), submodule.version_req()) {
+            (Some(submodule_name), Some(version_req)) => (submodule_name, version_req),
+            _ => continue,
+        };
+        if name.is_some_and(|name| name != submodule_name) {
+            continue;
+        }
+        match submodule.update_to_version(context)? {
+            Some(tag) => {
+                println_info!("Updated submodule `{submodule_name}` to tag `{tag}`");
+            }
+            None => {
+                println_warn!(
+                    "No tag in submodule `{submodule_name}` satisfies `{version_req}`, not updated"
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Convert a line from [`GitContext::log_oneline`] to a Markdown list item
+fn changelog_item(line: &str) -> String {
+    match line.split_once(' ') {
+        Some((sha, subject)) => format!("- {subject} (`{sha}`)\n"),
+        None => format!("- `{line}`\n"),
+    }
+}
+
+/// Find the submodule with the given name
+///
+/// If not found, an error is printed, with a hint if the name is the path of a submodule
+fn find_submodule<'a>(
+    status: &'a mut Status,
+    name: &str,
+    command: &str,
+) -> Result<&'a mut Submodule, GitError> {
+    if !status.modules.contains_key(name) {
+        println_error!("Submodule `{name}` not found!");
+        // maybe user passed in path instead of name?
+        println_verbose!("Trying to search for a path matching `{name}`");
+        for submodule in status.flattened() {
+            if let Some(other_name) = submodule.name()
+                && let Some(path) = submodule.path()
+                && path == name
+            {
+                println_hint!(
+                    "  however, there is a submodule \"{other_name}\" with path \"{path}\""
+                );
+                println_hint!(
+                    "  if you meant to {command} this submodule, use `magoo {command} {other_name}`"
+                );
+                break;
+            }
+        }
+
+        return Err(GitError::NeedFix(false));
+    }
+    Ok(status.modules.get_mut(name).unwrap())
+}
+
+/// Check if the submodule is healthy, and print an error if not. Unhealthy submodules are
+/// allowed with a warning if `bypass` is true
+fn check_healthy(
+    context: &GitContext,
+    submodule: &Submodule,
+    name: &str,
+    bypass: bool,
+) -> Result<(), GitError> {
+    if submodule.is_healthy(context)? {
+        return Ok(());
+    }
+    if !bypass {
+        println_error!("Submodule `{name}` is not healthy!");
+        println_hint!(
+            "  run `magoo status` to investigate. Some issues might be fixable with `magoo status --fix`."
+        );
+        println_hint!("  alternatively, use the `--bypass` flag to ignore and continue anyway.");
+        return Err(GitError::NeedFix(false));
+    }
+    println_warn!("Bypassing warnings from unhealthy submodule `{name}`");
+    Ok(())
+}
+
+/// Check if the submodule has uncommitted changes, and print an error if so
+fn is_dirty(context: &GitContext, submodule: &Submodule) -> Result<bool, GitError> {
+    let changes = match submodule.worktree_changes(context)? {
+        Some(changes) if !changes.is_clean() => changes,
+        _ => return Ok(false),
+    };
+    let name = submodule.name().or(submodule.path()).unwrap_or("<unknown>");
+    println_error!(
+        "Submodule `{name}` has uncommitted changes ({})",
+        changes.describe()
+    );
+    println_hint!(
+        "  commit or stash the changes in the submodule, or use the `--force` flag to discard them"
+    );
+    Ok(true)
+}
+
 /// Printing options for all commands
 #[derive(Debug, Default, Clone, PartialEq)]
 #[cfg_attr(feature = "cli", derive(clap::Parser))]
@@ -11,6 +11,9 @@ fn main() {
         if let GitError::NeedFix(false) = e {
             exit(1)
         }
+        if let GitError::CheckFailed(failure) = e {
+            exit(failure.exit_code())
+        }
         println!("magoo: fatal:");
         println!("  {e}");
         exit(2)
@@ -0,0 +1,174 @@
+//! Manifest of the submodules in a repository, for exporting and importing the submodule set
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::git::GitError;
+use crate::print::println_warn;
+use crate::status::Status;
+
+/// The set of submodules in a repository
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The submodules, sorted by name
+    #[serde(default)]
+    pub submodules: Vec<ManifestEntry>,
+}
+
+/// One submodule in a [`Manifest`]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Name of the submodule
+    pub name: String,
+    /// Path of the submodule from the top level of the repository
+    pub path: String,
+    /// URL of the submodule
+    pub url: String,
+    /// Branch to update the submodule from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// The commit of the submodule in the index
+    pub commit: String,
+    /// If the submodule should be cloned shallowly (`submodule.<name>.shallow` in .gitmodules)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub shallow: bool,
+    /// Semver requirement of the release tag to update the submodule to
+    /// (`submodule.<name>.magoo-version` in .gitmodules)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// Format of a manifest file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ManifestFormat {
+    /// JSON
+    Json,
+    /// TOML
+    Toml,
+}
+
+impl ManifestFormat {
+    /// Get the format from the extension of the file. TOML is used for `.toml` files, and JSON
+    /// for everything else
+    pub fn from_path<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        match path.as_ref().extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Self::Toml,
+            _ => Self::Json,
+        }
+    }
+}
+
+impl Manifest {
+    /// Create the manifest from the submodules in the status.
+    ///
+    /// Submodules that are missing the path, URL or commit are skipped with a warning. The URL
+    /// in .gitmodules is recorded, so relative URLs stay relative
+    pub fn from_status(status: &Status) -> Self {
+        let mut submodules = Vec::new();
+        for submodule in status.flattened() {
+            let name = match submodule.name() {
+                Some(name) => name,
+                None => {
+                    println_warn!(
+                        "Skipping submodule at `{}` that is not in .gitmodules",
+                        submodule.path().unwrap_or("<unknown>")
+                    );
+                    continue;
+                }
+            };
+            let (path, url, commit) = match (
+                submodule.path(),
+                submodule.versioned_url(),
+                submodule.index_commit(),
+            ) {
+                (Some(path), Some(url), Some(commit)) => (path, url, commit),
+                _ => {
+                    println_warn!(
+                        "Skipping submodule `{name}` that is missing the path, URL or commit"
+                    );
+                    continue;
+                }
+            };
+            submodules.push(ManifestEntry {
+                name: name.to_string(),
+                path: path.to_string(),
+                url: url.to_string(),
+                branch: submodule.branch().map(|x| x.to_string()),
+                commit: commit.to_string(),
+                shallow: submodule.shallow(),
+                version: submodule.version_req().map(|x| x.to_string()),
+            });
+        }
+        Self { submodules }
+    }
+
+    /// Parse the manifest
+    pub fn parse(content: &str, format: ManifestFormat) -> Result<Self, GitError> {
+        match format {
+            ManifestFormat::Json => {
+                serde_json::from_str(content).map_err(|e| GitError::InvalidManifest(e.to_string()))
+            }
+            ManifestFormat::Toml => {
+                toml::from_str(content).map_err(|e| GitError::InvalidManifest(e.to_string()))
+            }
+        }
+    }
+
+    /// Serialize the manifest
+    pub fn to_string(&self, format: ManifestFormat) -> Result<String, GitError> {
+        match format {
+            ManifestFormat::Json => serde_json::to_string_pretty(self)
+                .map(|x| x + "\n")
+                .map_err(GitError::InvalidJson),
+            ManifestFormat::Toml => {
+                toml::to_string(self).map_err(|e| GitError::InvalidManifest(e.to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let manifest = Manifest {
+            submodules: vec![
+                ManifestEntry {
+                    name: "a".to_string(),
+                    path: "libs/a".to_string(),
+                    url: "https://example.com/a.git".to_string(),
+                    branch: Some("main".to_string()),
+                    commit: "0123456789abcdef0123456789abcdef01234567".to_string(),
+                    shallow: true,
+                    version: Some("^1.4".to_string()),
+                },
+                ManifestEntry {
+                    name: "b".to_string(),
+                    path: "b".to_string(),
+                    url: "../b.git".to_string(),
+                    commit: "89abcdef0123456789abcdef0123456789abcdef".to_string(),
+                    ..Default::default()
+                },
+            ],
+        };
+        for format in [ManifestFormat::Json, ManifestFormat::Toml] {
+            let content = manifest.to_string(format).unwrap();
+            assert_eq!(Manifest::parse(&content, format).unwrap(), manifest);
+        }
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ManifestFormat::from_path("deps.toml"), ManifestFormat::Toml);
+        assert_eq!(ManifestFormat::from_path("deps.TOML"), ManifestFormat::Toml);
+        assert_eq!(ManifestFormat::from_path("deps.json"), ManifestFormat::Json);
+        assert_eq!(ManifestFormat::from_path("deps"), ManifestFormat::Json);
+    }
+}
@@ -0,0 +1,276 @@
+//! Planned changes to the repository, so destructive operations can be previewed with `--dry-run`
+//! before running them
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::git::{self, GitCmdPath, GitContext, GitError, quote_arg};
+use crate::print::{println_info, println_verbose};
+use crate::trash::Trash;
+
+/// A change to the repository
+///
+/// Git commands are run from the top level directory of the repository.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Run `git submodule absorbgitdirs -- <path>` to move the embedded git directory of the
+    /// submodule at the path into .git/modules
+    AbsorbGitDir(String),
+    /// Remove a directory by moving it to the trash, see [`Trash`]
+    RemoveDir(PathBuf),
+    /// Run `git config -f <file> --remove-section <section>`. Errors are ignored, since the
+    /// section might not exist
+    RemoveConfigSection(PathBuf, String),
+    /// Run `git config -f <file> <key> <value>`
+    SetConfig(PathBuf, String, String),
+    /// Run `git add <path>`. Errors are ignored, since the path might not exist
+    Add(String),
+    /// Run `git rm <path>` and `git add <path>` to remove the path from the index and stage the
+    /// change
+    RemoveFromIndex(String),
+    /// Run `git submodule add`
+    SubmoduleAdd {
+        url: String,
+        path: Option<String>,
+        branch: Option<String>,
+        name: Option<String>,
+        depth: Option<usize>,
+        force: bool,
+    },
+    /// Run `git submodule deinit [--force] -- <path>`
+    SubmoduleDeinit { path: String, force: bool },
+    /// Run `git submodule init`
+    SubmoduleInit,
+    /// Run `git submodule sync [--recursive]`
+    SubmoduleSync { recursive: bool },
+    /// Run `git submodule update [--force] [--init --recursive] [--jobs <jobs>]`
+    SubmoduleUpdate {
+        force: bool,
+        recursive: bool,
+        jobs: Option<usize>,
+    },
+}
+
+impl Action {
+    /// Run the action
+    pub fn run(&self, context: &GitContext) -> Result<(), GitError> {
+        match self {
+            Action::AbsorbGitDir(path) => {
+                println_info!("Absorbing the git directory of `{path}` into .git/modules");
+                context.submodule_absorbgitdirs(Some(path))?;
+            }
+            Action::RemoveDir(path) => {
+                if path.exists() {
+                    println_info!("Moving `{}` to the trash", path.to_cmd_arg());
+                    Trash::open(context)?.move_dir(path)?;
+                }
+            }
+            Action::RemoveConfigSection(file, section) => {
+                println_info!("Deleting `{section}` in `{}`", file.to_cmd_arg());
+                let _ = context.remove_config_section(file, section);
+            }
+            Action::SetConfig(file, key, value) => {
+                println_verbose!("Setting `{key}` to `{value}` in `{}`", file.to_cmd_arg());
+                context.set_config(file, key, Some(value))?;
+            }
+            Action::Add(path) => {
+                let _ = context.add(path);
+            }
+            Action::RemoveFromIndex(path) => {
+                println_info!("Deleting `{path}` in index");
+                context.remove_from_index(path)?;
+            }
+            Action::SubmoduleAdd {
+                url,
+                path,
+                branch,
+                name,
+                depth,
+                force,
+            } => {
+                println_verbose!("Adding submodule from url: {url}");
+                context.submodule_add(
+                    url,
+                    path.as_deref(),
+                    branch.as_deref(),
+                    name.as_deref(),
+                    *depth,
+                    *force,
+                )?;
+            }
+            Action::SubmoduleDeinit { path, force } => {
+                context.submodule_deinit(Some(path), *force)?;
+            }
+            Action::SubmoduleInit => {
+                context.submodule_init(None)?;
+            }
+            Action::SubmoduleSync { recursive } => {
+                context.submodule_sync(None, *recursive)?;
+            }
+            Action::SubmoduleUpdate {
+                force,
+                recursive,
+                jobs,
+            } => {
+                context.submodule_update(&[], *force, false, *recursive, *jobs)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the arguments of the git commands the action runs. Empty if the action doesn't run
+    /// git
+    pub fn git_commands(&self) -> Vec<Vec<String>> {
+        match self {
+            Action::AbsorbGitDir(path) => vec![git::submodule_absorbgitdirs_args(Some(path))],
+            Action::RemoveDir(_) => vec![],
+            Action::RemoveConfigSection(file, section) => {
+                vec![git::config_remove_section_args(file, section)]
+            }
+            Action::SetConfig(file, key, value) => {
+                vec![git::config_set_args(file, key, Some(value))]
+            }
+            Action::Add(path) => vec![git::add_args(path)],
+            Action::RemoveFromIndex(path) => vec![git::rm_args(path), git::add_args(path)],
+            Action::SubmoduleAdd {
+                url,
+                path,
+                branch,
+                name,
+                depth,
+                force,
+            } => vec![git::submodule_add_args(
+                url,
+                path.as_deref(),
+                branch.as_deref(),
+                name.as_deref(),
+                *depth,
+                *force,
+            )],
+            Action::SubmoduleDeinit { path, force } => {
+                vec![git::submodule_deinit_args(Some(path), *force)]
+            }
+            Action::SubmoduleInit => vec![git::submodule_init_args(None)],
+            Action::SubmoduleSync { recursive } => {
+                vec![git::submodule_sync_args(None, *recursive)]
+            }
+            Action::SubmoduleUpdate {
+                force,
+                recursive,
+                jobs,
+            } => vec![git::submodule_update_args(
+                &[],
+                *force,
+                false,
+                *recursive,
+                *jobs,
+            )],
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Action::RemoveDir(path) = self {
+            return write!(f, "move {} to the trash", quote_arg(&path.to_cmd_arg()));
+        }
+        let commands = self
+            .git_commands()
+            .iter()
+            .map(|command| {
+                let args = command
+                    .iter()
+                    .map(|x| quote_arg(x).into_owned())
+                    .collect::<Vec<_>>();
+                format!("git {}", args.join(" "))
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", commands.join(" && "))
+    }
+}
+
+/// Remove the [`Action::Add`] actions that are repeated later for the same path, since only the
+/// last one is needed to stage the final state
+pub fn dedup_adds(actions: &mut Vec<Action>) {
+    let mut result = Vec::with_capacity(actions.len());
+    for (i, action) in actions.iter().enumerate() {
+        if matches!(action, Action::Add(_)) && actions[i + 1..].contains(action) {
+            continue;
+        }
+        result.push(action.clone());
+    }
+    *actions = result;
+}
+
+/// Run the actions in order, stopping at the first error
+pub fn run_actions(context: &GitContext, actions: &[Action]) -> Result<(), GitError> {
+    for action in actions {
+        action.run(context)?;
+    }
+    Ok(())
+}
+
+/// Print the actions, one per line with the indent
+pub fn print_actions(actions: &[Action], indent: &str) {
+    for action in actions {
+        println_info!("{indent}{action}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup_adds() {
+        let mut actions = vec![
+            Action::RemoveFromIndex("foo".to_string()),
+            Action::Add(".gitmodules".to_string()),
+            Action::RemoveConfigSection(PathBuf::from(".gitmodules"), "submodule.foo".to_string()),
+            Action::Add(".gitmodules".to_string()),
+        ];
+        dedup_adds(&mut actions);
+        assert_eq!(
+            actions,
+            vec![
+                Action::RemoveFromIndex("foo".to_string()),
+                Action::RemoveConfigSection(
+                    PathBuf::from(".gitmodules"),
+                    "submodule.foo".to_string()
+                ),
+                Action::Add(".gitmodules".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let action = Action::RemoveConfigSection(
+            PathBuf::from("/repo/.git/config"),
+            "submodule.foo".to_string(),
+        );
+        assert_eq!(
+            action.to_string(),
+            "git config -f /repo/.git/config --remove-section submodule.foo"
+        );
+        let action = Action::RemoveFromIndex("my lib".to_string());
+        assert_eq!(action.to_string(), "git rm 'my lib' && git add 'my lib'");
+        let action = Action::SubmoduleAdd {
+            url: "https://example.com/a.git".to_string(),
+            path: Some("libs/a".to_string()),
+            branch: Some("main".to_string()),
+            name: None,
+            depth: None,
+            force: false,
+        };
+        assert_eq!(
+            action.to_string(),
+            "git submodule add --branch main -- https://example.com/a.git libs/a"
+        );
+        let action = Action::RemoveDir(PathBuf::from("/repo/.git/modules/foo"));
+        assert_eq!(
+            action.to_string(),
+            "move /repo/.git/modules/foo to the trash"
+        );
+    }
+}
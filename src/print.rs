@@ -50,6 +50,10 @@ pub fn stdout() -> StandardStream {
     unsafe { StandardStream::stdout(COLOR_CHOICE) }
 }
 
+pub fn stderr() -> StandardStream {
+    unsafe { StandardStream::stderr(COLOR_CHOICE) }
+}
+
 #[inline]
 pub fn is_quiet() -> bool {
     unsafe { QUIET && !VERBOSE }
@@ -94,6 +98,18 @@ macro_rules! println_info {
 }
 pub(crate) use println_info;
 
+/// Print to stderr without a newline
+macro_rules! eprint_info {
+    ($($args:tt)*) => {
+        if !$crate::print::is_quiet() {
+            use std::io::Write;
+            let stderr = $crate::print::stderr();
+            let _ = write!(&mut stderr.lock(), $($args)*);
+        }
+    };
+}
+pub(crate) use eprint_info;
+
 /// Print using info color
 macro_rules! print_info {
     ($($args:tt)*) => {
@@ -9,6 +9,7 @@ use crate::submodule::*;
 
 /// Status of all submodules in a repository
 #[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Status {
     /// The submodule status map from name to [`Submodule`]
     pub modules: BTreeMap<String, Submodule>,
@@ -37,6 +38,41 @@ macro_rules! insert_with_name {
     }};
 }
 
+/// Format passed to `git ls-files` and `git ls-tree` to list objects for [`parse_gitlinks`]
+const GITLINK_FORMAT: &str = "--format=%(objectmode) %(objectname) %(path)";
+
+/// Parse the output of `git ls-files` or `git ls-tree` with [`GITLINK_FORMAT`] and return the
+/// submodules (objects with mode 160000)
+fn parse_gitlinks<S>(lines: &[S]) -> Result<Vec<IndexObject>, GitError>
+where
+    S: AsRef<str>,
+{
+    let mut index_objects = Vec::new();
+    for line in lines {
+        // mode 160000 is submodule
+        let line = match line.as_ref().strip_prefix("160000 ") {
+            Some(line) => line,
+            None => {
+                continue;
+            }
+        };
+        println_verbose!("Found submodule in index: {}", line);
+        let mut parts = line.splitn(2, ' ');
+        let sha = parts
+            .next()
+            .ok_or_else(|| GitError::InvalidIndex("missing commit hash in output".to_string()))?;
+        let path = parts
+            .next()
+            .ok_or_else(|| GitError::InvalidIndex("missing path in output".to_string()))?;
+
+        index_objects.push(IndexObject {
+            sha: sha.to_string(),
+            path: path.to_string(),
+        });
+    }
+    Ok(index_objects)
+}
+
 impl Status {
     /// Return a flattened view of all the submodules
     pub fn flattened(&self) -> Vec<&Submodule> {
@@ -65,6 +101,57 @@ impl Status {
         modules
     }
 
+    /// Find a submodule by name, or by path if no submodule has the name
+    pub fn find(&self, name_or_path: &str) -> Option<&Submodule> {
+        self.modules.get(name_or_path).or_else(|| {
+            self.flattened()
+                .into_iter()
+                .find(|x| x.path() == Some(name_or_path))
+        })
+    }
+
+    /// Find the git repositories in the worktree that are not registered as submodules, like
+    /// repositories cloned by hand. Repositories added to the index without .gitmodules are
+    /// included
+    pub fn find_embedded_repos(&self, context: &GitContext) -> Result<Vec<EmbeddedRepo>, GitError> {
+        let top_level_dir = context.top_level_dir()?;
+        // untracked repositories are listed as directories, since git doesn't descend into them
+        let mut paths = context
+            .ls_files(&["--others", "--exclude-standard"])?
+            .into_iter()
+            .filter_map(|x| x.strip_suffix('/').map(|x| (x.to_string(), false)))
+            .collect::<Vec<_>>();
+        paths.extend(
+            self.nameless_objects()
+                .into_iter()
+                .map(|x| (x.path.clone(), true)),
+        );
+
+        let mut repos = Vec::new();
+        for (path, in_index) in paths {
+            let dir = top_level_dir.join(&path);
+            if !dir.join(".git").exists() {
+                continue;
+            }
+            let repo_context = match GitContext::try_from(&dir) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            let url = match repo_context.default_remote() {
+                Ok(remote) => repo_context.remote_url(&remote),
+                Err(_) => None,
+            };
+            repos.push(EmbeddedRepo {
+                path,
+                url,
+                branch: repo_context.current_branch(),
+                in_index,
+            });
+        }
+        repos.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(repos)
+    }
+
     /// Get a view of the submodules that only exist in the index
     pub fn nameless_objects(&self) -> Vec<&IndexObject> {
         self.nameless
@@ -83,6 +170,55 @@ impl Status {
         Ok(true)
     }
 
+    /// Create a machine-readable report of the status, with the issues of each submodule
+    pub fn report(&self, context: &GitContext) -> Result<StatusReport, GitError> {
+        let mut submodules = Vec::new();
+        for submodule in self.flattened() {
+            submodules.push(SubmoduleReport::new(submodule, context)?);
+        }
+        Ok(StatusReport { submodules })
+    }
+
+    /// Get the submodule status in the repository, and recursively in every initialized submodule
+    pub fn read_recursive(context: &GitContext) -> Result<StatusTree, GitError> {
+        let status = Self::read_from(context)?;
+        let mut children = BTreeMap::new();
+        for submodule in status.flattened() {
+            let path = match submodule.path() {
+                Some(path) => path,
+                None => continue,
+            };
+            if let Some(sub_context) = submodule.open(context) {
+                println_verbose!("Reading nested submodules in `{path}`");
+                children.insert(path.to_string(), Self::read_recursive(&sub_context)?);
+            }
+        }
+        Ok(StatusTree { status, children })
+    }
+
+    /// Get the submodules recorded in a revision of the repository, or in the index if `rev` is
+    /// [`None`]
+    ///
+    /// Only the data in `.gitmodules` and the gitlinks are read, since the other parts
+    /// are not versioned.
+    pub fn read_from_revision(context: &GitContext, rev: Option<&str>) -> Result<Self, GitError> {
+        let mut status = Self::default();
+        let blob = format!("{}:.gitmodules", rev.unwrap_or_default());
+        match context.get_blob_config_regexp(&blob, "submodule") {
+            Ok(entries) => status.add_dot_gitmodules_entries(Self::strip_submodule_prefix(entries)),
+            Err(e) => {
+                println_verbose!("Cannot read `{blob}`, assuming no submodules: {e}");
+            }
+        }
+        let list = match rev {
+            Some(rev) => context.ls_tree(rev, &[GITLINK_FORMAT])?,
+            None => context.ls_files(&[GITLINK_FORMAT])?,
+        };
+        status.add_index_objects(parse_gitlinks(&list)?);
+
+        Ok(status)
+    }
+
     /// Factory function. Get the submodule status in the repository.
     pub fn read_from(context: &GitContext) -> Result<Self, GitError> {
         let mut status = Self::default();
@@ -103,7 +239,12 @@ impl Status {
         let config_entries =
             Self::read_submodule_from_config(context, &dot_gitmodules_path.to_cmd_arg())
                 .unwrap_or_default();
+        self.add_dot_gitmodules_entries(config_entries);
+        Ok(())
+    }
 
+    /// Put the entries from `.gitmodules` (with the `submodule.` prefix removed) into self
+    fn add_dot_gitmodules_entries(&mut self, config_entries: Vec<(String, String)>) {
         for (key, value) in config_entries {
             let name = if let Some(name) = key.strip_suffix(".path") {
                 insert_with_name!(&mut self.modules, name).path = Some(value);
@@ -114,13 +255,18 @@ impl Status {
             } else if let Some(name) = key.strip_suffix(".branch") {
                 insert_with_name!(&mut self.modules, name).branch = Some(value);
                 name
+            } else if let Some(name) = key.strip_suffix(".shallow") {
+                insert_with_name!(&mut self.modules, name).shallow = Some(value == "true");
+                name
+            } else if let Some(name) = key.strip_suffix(".magoo-version") {
+                insert_with_name!(&mut self.modules, name).version = Some(value);
+                name
             } else {
                 continue;
             };
 
             println_verbose!("Found submodule in .gitmodules: {name}");
         }
-        Ok(())
     }
 
     /// Read the `.git/config` data into self
@@ -175,16 +321,19 @@ impl Status {
         config_path: &str,
     ) -> Result<Vec<(String, String)>, GitError> {
         let name_values = context.get_config_regexp(config_path, "submodule")?;
-        let name_values = name_values
+        Ok(Self::strip_submodule_prefix(name_values))
+    }
+
+    /// Keep the config entries that start with "submodule." and remove the prefix
+    fn strip_submodule_prefix(name_values: Vec<(String, String)>) -> Vec<(String, String)> {
+        name_values
             .into_iter()
             .filter_map(|(name, value)| {
                 let name = name.strip_prefix("submodule.")?;
                 println_verbose!("Found submodule config: {} => {}", name, value);
                 Some((name.to_string(), value))
             })
-            .collect::<Vec<_>>();
-
-        Ok(name_values)
+            .collect::<Vec<_>>()
     }
 
     /// Read .git/modules and find all entries and put them in self
@@ -276,7 +425,10 @@ impl Status {
     }
 
     /// Read `.git/modules/<name>`
-    fn read_git_module(name: &str, context: &GitContext) -> Result<InGitModule, GitError> {
+    pub(crate) fn read_git_module(
+        name: &str,
+        context: &GitContext,
+    ) -> Result<InGitModule, GitError> {
         let git_dir = context.git_dir()?;
         let module_dir = git_dir.join("modules").join(name);
         if !module_dir.exists() {
@@ -324,35 +476,18 @@ impl Status {
 
     /// Use `git ls-files` to list submodules stored in the index into self
     fn read_submodules_in_index(&mut self, context: &GitContext) -> Result<(), GitError> {
-        let index_list = context.ls_files(&[r#"--format=%(objectmode) %(objectname) %(path)"#])?;
-
-        let mut path_to_index_object = BTreeMap::new();
+        let index_list = context.ls_files(&[GITLINK_FORMAT])?;
+        self.add_index_objects(parse_gitlinks(&index_list)?);
+        Ok(())
+    }
 
-        for line in index_list {
-            // mode 160000 is submodule
-            let line = match line.strip_prefix("160000 ") {
-                Some(line) => line,
-                None => {
-                    continue;
-                }
-            };
-            println_verbose!("Found submodule in index: {}", line);
-            let mut parts = line.splitn(2, ' ');
-            let sha = parts.next().ok_or_else(|| {
-                GitError::InvalidIndex("missing commit hash in output".to_string())
-            })?;
-            let path = parts
-                .next()
-                .ok_or_else(|| GitError::InvalidIndex("missing path in output".to_string()))?;
-
-            path_to_index_object.insert(
-                path.to_string(),
-                IndexObject {
-                    sha: sha.to_string(),
-                    path: path.to_string(),
-                },
-            );
-        }
+    /// Connect the index objects to the submodules in self by path. Objects that don't match any
+    /// submodule are added as nameless
+    fn add_index_objects(&mut self, index_objects: Vec<IndexObject>) {
+        let mut path_to_index_object = index_objects
+            .into_iter()
+            .map(|x| (x.path.clone(), x))
+            .collect::<BTreeMap<_, _>>();
 
         for submodule in self.modules.values_mut() {
             let path = match submodule.path() {
@@ -377,6 +512,166 @@ impl Status {
                 in_modules: None,
             });
         }
+    }
+}
+
+/// A git repository in the worktree that is not registered as a submodule
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EmbeddedRepo {
+    /// Path of the repository from the top level of the superproject
+    pub path: String,
+    /// URL of the default remote of the repository
+    pub url: Option<String>,
+    /// The branch checked out in the repository
+    pub branch: Option<String>,
+    /// If the repository is added to the index as a gitlink, without .gitmodules
+    pub in_index: bool,
+}
+
+/// Status of submodules in a repository, and the status of nested submodules in each
+/// initialized submodule
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StatusTree {
+    /// Status of the submodules in this repository
+    pub status: Status,
+    /// Status of the nested submodules, by the path of the submodule in this repository
+    pub children: BTreeMap<String, StatusTree>,
+}
+
+impl From<Status> for StatusTree {
+    fn from(status: Status) -> Self {
+        Self {
+            status,
+            children: BTreeMap::new(),
+        }
+    }
+}
+
+impl StatusTree {
+    /// Get the nested status of a submodule in this repository
+    pub fn child(&self, submodule: &Submodule) -> Option<&StatusTree> {
+        self.children.get(submodule.path()?)
+    }
+
+    /// Check if all submodules, including nested ones, are healthy
+    pub fn is_healthy(&self, context: &GitContext) -> Result<bool, GitError> {
+        let mut healthy = true;
+        self.walk(context, &mut |context, submodule, _| {
+            healthy &= submodule.is_healthy(context)?;
+            Ok(())
+        })?;
+        Ok(healthy)
+    }
+
+    /// Visit every submodule in the tree, parent before nested submodules
+    ///
+    /// The callback is called with the context of the repository containing the submodule, the
+    /// submodule, and the depth of the submodule (0 for submodules in the root repository)
+    pub fn walk<F>(&self, context: &GitContext, f: &mut F) -> Result<(), GitError>
+    where
+        F: FnMut(&GitContext, &Submodule, usize) -> Result<(), GitError>,
+    {
+        self.walk_at_depth(context, 0, f)
+    }
+
+    fn walk_at_depth<F>(
+        &self,
+        context: &GitContext,
+        depth: usize,
+        f: &mut F,
+    ) -> Result<(), GitError>
+    where
+        F: FnMut(&GitContext, &Submodule, usize) -> Result<(), GitError>,
+    {
+        for submodule in self.status.flattened() {
+            f(context, submodule, depth)?;
+            if let Some(child) = self.child(submodule)
+                && let Some(sub_context) = submodule.open(context)
+            {
+                child.walk_at_depth(&sub_context, depth + 1, f)?;
+            }
+        }
         Ok(())
     }
+
+    /// Create a machine-readable report of the status, including nested submodules
+    pub fn report(&self, context: &GitContext) -> Result<StatusReport, GitError> {
+        let mut submodules = Vec::new();
+        for submodule in self.status.flattened() {
+            let mut report = SubmoduleReport::new(submodule, context)?;
+            if let Some(child) = self.child(submodule)
+                && let Some(sub_context) = submodule.open(context)
+            {
+                report.nested = Some(child.report(&sub_context)?);
+            }
+            submodules.push(report);
+        }
+        Ok(StatusReport { submodules })
+    }
+}
+
+/// Machine-readable report of the status of all submodules. This is what
+/// `magoo status --format json` prints.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StatusReport {
+    /// Report of each submodule, in the same order as [`Status::flattened`]
+    pub submodules: Vec<SubmoduleReport>,
+}
+
+/// Report of one submodule in a [`StatusReport`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SubmoduleReport {
+    /// See [`Submodule::name`]
+    pub name: Option<String>,
+    /// See [`Submodule::path`]
+    pub path: Option<String>,
+    /// See [`Submodule::url`]
+    pub url: Option<String>,
+    /// See [`Submodule::branch`]
+    pub branch: Option<String>,
+    /// See [`Submodule::index_commit`]
+    pub index_commit: Option<String>,
+    /// See [`Submodule::head_commit`]
+    pub head_commit: Option<String>,
+    /// See [`Submodule::upstream_counts`]
+    pub upstream: Option<UpstreamCounts>,
+    /// See [`Submodule::worktree_changes`]
+    pub changes: Option<WorktreeChanges>,
+    /// See [`Submodule::is_healthy`]
+    pub healthy: bool,
+    /// See [`Submodule::issues`]
+    pub issues: Vec<Issue>,
+    /// See [`Submodule::parts`]
+    pub present: SubmoduleParts,
+    /// The raw data of the submodule
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub parts: Submodule,
+    /// Report of the nested submodules, only when reading the status recursively
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub nested: Option<StatusReport>,
+}
+
+impl SubmoduleReport {
+    /// Create the report for a submodule
+    pub fn new(submodule: &Submodule, context: &GitContext) -> Result<Self, GitError> {
+        let issues = submodule.issues(context)?;
+        Ok(Self {
+            name: submodule.name().map(ToString::to_string),
+            path: submodule.path().map(ToString::to_string),
+            url: submodule.url().map(ToString::to_string),
+            branch: submodule.branch().map(ToString::to_string),
+            index_commit: submodule.index_commit().map(ToString::to_string),
+            head_commit: submodule.head_commit().map(ToString::to_string),
+            upstream: submodule.upstream_counts(context)?,
+            changes: submodule.worktree_changes(context)?,
+            healthy: issues.is_empty(),
+            issues,
+            present: submodule.parts(),
+            parts: submodule.clone(),
+            nested: None,
+        })
+    }
 }
@@ -1,15 +1,21 @@
 //! Submodule data and operations
 
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
+use semver::VersionReq;
+
+pub use crate::check::{CheckFailure, Issue};
 use crate::git::{GitCanonicalize, GitCmdPath, GitContext, GitError, quote_arg};
+use crate::plan::{Action, dedup_adds, run_actions};
 use crate::print::{
     print_info, print_warn, println_error, println_hint, println_info, println_verbose,
     println_warn,
 };
+use crate::version;
 
 /// Collection of data of a submodule with the same name as identifier
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Submodule {
     /// Data of this submodule in .gitmodules
     pub in_gitmodules: Option<InGitmodules>,
@@ -48,18 +54,18 @@ impl Submodule {
     /// 2. Path in the index object
     /// 3. Path in .git/modules/<name>/config (core.worktree)
     pub fn path(&self) -> Option<&str> {
-        if let Some(gitmodules) = &self.in_gitmodules {
-            if let Some(path) = &gitmodules.path {
-                return Some(path.as_str());
-            }
+        if let Some(gitmodules) = &self.in_gitmodules
+            && let Some(path) = &gitmodules.path
+        {
+            return Some(path.as_str());
         }
         if let Some(index) = &self.in_index {
             return Some(index.path.as_str());
         }
-        if let Some(modules) = &self.in_modules {
-            if let Some(worktree) = &modules.worktree {
-                return Some(worktree.as_str());
-            }
+        if let Some(modules) = &self.in_modules
+            && let Some(worktree) = &modules.worktree
+        {
+            return Some(worktree.as_str());
         }
         None
     }
@@ -73,24 +79,51 @@ impl Submodule {
         if let Some(config) = &self.in_config {
             return Some(config.url.as_str());
         }
-        if let Some(gitmodules) = &self.in_gitmodules {
-            if let Some(url) = &gitmodules.url {
-                return Some(url.as_str());
-            }
+        if let Some(gitmodules) = &self.in_gitmodules
+            && let Some(url) = &gitmodules.url
+        {
+            return Some(url.as_str());
         }
         None
     }
 
+    /// Get the URL of the submodule as committed in .gitmodules, which could be relative, or
+    /// the URL in .git/config if it is not in .gitmodules
+    pub fn versioned_url(&self) -> Option<&str> {
+        if let Some(gitmodules) = &self.in_gitmodules
+            && let Some(url) = &gitmodules.url
+        {
+            return Some(url.as_str());
+        }
+        self.url()
+    }
+
     /// Get the update branch of the submodule defined in .gitmodules
     pub fn branch(&self) -> Option<&str> {
-        if let Some(gitmodules) = &self.in_gitmodules {
-            if let Some(branch) = &gitmodules.branch {
-                return Some(branch.as_str());
-            }
+        if let Some(gitmodules) = &self.in_gitmodules
+            && let Some(branch) = &gitmodules.branch
+        {
+            return Some(branch.as_str());
         }
         None
     }
 
+    /// Get the semver requirement of the release tag to update the submodule to, defined in
+    /// .gitmodules
+    pub fn version_req(&self) -> Option<&str> {
+        self.in_gitmodules
+            .as_ref()
+            .and_then(|x| x.version.as_deref())
+    }
+
+    /// Get if the submodule is recommended to be cloned shallowly, defined in .gitmodules
+    pub fn shallow(&self) -> bool {
+        self.in_gitmodules
+            .as_ref()
+            .and_then(|x| x.shallow)
+            .unwrap_or_default()
+    }
+
     /// Get the commit of the submodule in the index
     pub fn index_commit(&self) -> Option<&str> {
         if let Some(index) = &self.in_index {
@@ -106,10 +139,10 @@ impl Submodule {
 
     /// Get the commit currently checked out
     pub fn head_commit(&self) -> Option<&str> {
-        if let Some(modules) = &self.in_modules {
-            if let Some(head_sha) = &modules.head_sha {
-                return Some(head_sha.as_str());
-            }
+        if let Some(modules) = &self.in_modules
+            && let Some(head_sha) = &modules.head_sha
+        {
+            return Some(head_sha.as_str());
         }
         None
     }
@@ -119,35 +152,320 @@ impl Submodule {
         self.head_commit().map(|s| &s[..7])
     }
 
+    /// Open a [`GitContext`] in the worktree of the submodule. Returns [`None`] if the submodule
+    /// is not initialized
+    pub fn open(&self, context: &GitContext) -> Option<GitContext> {
+        self.head_commit()?;
+        let path = self.path()?;
+        let top_level_dir = context.top_level_dir().ok()?;
+        GitContext::try_from(top_level_dir.join(path)).ok()
+    }
+
+    /// Get the remote and the branch the submodule tracks for updates. Returns [`None`] if the
+    /// submodule doesn't have a branch configured or is not initialized
+    ///
+    /// The branch `.` is resolved to the current branch of the superproject, same as
+    /// `git submodule update --remote`.
+    pub fn upstream(&self, context: &GitContext) -> Result<Option<(String, String)>, GitError> {
+        let branch = match self.branch() {
+            Some(".") => match context.current_branch() {
+                Some(branch) => branch,
+                None => return Ok(None),
+            },
+            Some(branch) => branch.to_string(),
+            None => return Ok(None),
+        };
+        let sub_context = match self.open(context) {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let remote = sub_context.default_remote()?;
+        Ok(Some((remote, branch)))
+    }
+
+    /// Fetch the tracked branch of the submodule from its remote, if it has one
+    pub fn fetch_upstream(&self, context: &GitContext) -> Result<(), GitError> {
+        let (remote, branch) = match self.upstream(context)? {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        if let Some(sub_context) = self.open(context) {
+            println_verbose!(
+                "Fetching `{branch}` from `{remote}` for submodule `{}`",
+                self.name().unwrap_or_default()
+            );
+            sub_context.fetch(&remote, Some(&branch))?;
+        }
+        Ok(())
+    }
+
+    /// Fetch the tracked branch of the submodule, or `HEAD` of the remote if no branch is
+    /// configured, and return the fetched commit. Returns [`None`] if the submodule is not
+    /// initialized
+    pub fn fetch_latest(&self, context: &GitContext) -> Result<Option<String>, GitError> {
+        let sub_context = match self.open(context) {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let (remote, refspec) = match self.upstream(context)? {
+            Some(x) => x,
+            None => (sub_context.default_remote()?, "HEAD".to_string()),
+        };
+        println_verbose!(
+            "Fetching `{refspec}` from `{remote}` for submodule `{}`",
+            self.name().unwrap_or_default()
+        );
+        sub_context.fetch(&remote, Some(&refspec))?;
+        Ok(sub_context.rev_parse("FETCH_HEAD"))
+    }
+
+    /// Get the commit of the remote-tracking ref of the tracked branch, or of the remote's
+    /// `HEAD` if no branch is configured, without fetching. Returns [`None`] if the submodule
+    /// is not initialized or the ref is not found
+    pub fn upstream_commit(&self, context: &GitContext) -> Result<Option<String>, GitError> {
+        let sub_context = match self.open(context) {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let (remote, branch) = match self.upstream(context)? {
+            Some(x) => x,
+            None => (sub_context.default_remote()?, "HEAD".to_string()),
+        };
+        Ok(sub_context.rev_parse(&format!("refs/remotes/{remote}/{branch}")))
+    }
+
+    /// Check out a commit in the submodule. The new commit is not staged in the index of the
+    /// superproject
+    ///
+    /// Full commit hashes and tags are only fetched from the remote if they are not found
+    /// locally. Other revisions, like branches, are fetched first since local refs can be stale.
+    pub fn checkout(&self, context: &GitContext, commit: &str) -> Result<(), GitError> {
+        let name = self.name().unwrap_or_default();
+        let sub_context = match self.open(context) {
+            Some(x) => x,
+            None => return Err(GitError::ModuleNotFound(name.to_string())),
+        };
+        let resolved = if is_fixed_rev(&sub_context, commit) {
+            match sub_context.rev_parse(commit) {
+                Some(x) => x,
+                None => fetch_rev(&sub_context, name, commit)?,
+            }
+        } else {
+            // branches can be stale locally, so they are resolved against the remote first
+            match fetch_rev(&sub_context, name, commit) {
+                Ok(x) => x,
+                Err(e) => {
+                    println_verbose!("Failed to fetch `{commit}`, resolving it locally");
+                    sub_context.rev_parse(commit).ok_or(e)?
+                }
+            }
+        };
+        sub_context.checkout_detach(&resolved)?;
+        Ok(())
+    }
+
+    /// Fetch the tags of the submodule and check out the highest tag that satisfies the semver
+    /// requirement in .gitmodules. Like [`Submodule::checkout`], the new commit is not staged.
+    ///
+    /// Returns the tag checked out, or [`None`] if no tag satisfies the requirement
+    pub fn update_to_version(&self, context: &GitContext) -> Result<Option<String>, GitError> {
+        let name = self.name().unwrap_or_default();
+        let (version_req, sub_context) = match (self.version_req(), self.open(context)) {
+            (Some(version_req), Some(sub_context)) => (version_req, sub_context),
+            _ => return Err(GitError::ModuleNotFound(name.to_string())),
+        };
+        let req = VersionReq::parse(version_req)
+            .map_err(|e| GitError::InvalidVersionReq(version_req.to_string(), e))?;
+        let remote = sub_context.default_remote()?;
+        println_verbose!("Fetching tags from `{remote}` for submodule `{name}`");
+        sub_context.fetch_tags(&remote)?;
+        let tags = sub_context.list_tags()?;
+        let tag = match version::find_highest_tag(&tags, &req) {
+            Some(tag) => tag.to_string(),
+            None => return Ok(None),
+        };
+        println_verbose!("Found tag `{tag}` for `{version_req}` in submodule `{name}`");
+        self.checkout(context, &format!("refs/tags/{tag}"))?;
+        Ok(Some(tag))
+    }
+
+    /// Check if the worktree of the submodule has a `.git` directory, instead of a `.git` file
+    /// that points to .git/modules/<name>. This happens if the submodule is cloned by older
+    /// tools or by hand
+    ///
+    /// Only submodules in .gitmodules are considered, since the others don't have a name to
+    /// absorb the git directory into, see [`is_unregistered_repo`](Self::is_unregistered_repo)
+    pub fn has_embedded_git_dir(&self, context: &GitContext) -> Result<bool, GitError> {
+        if self.in_gitmodules.is_none() {
+            return Ok(false);
+        }
+        self.has_worktree_git_dir(context)
+    }
+
+    /// Check if the submodule is only a gitlink in the index, with a git repository in the
+    /// worktree that is not in .gitmodules. It can be registered with `magoo adopt`
+    pub fn is_unregistered_repo(&self, context: &GitContext) -> Result<bool, GitError> {
+        if self.in_gitmodules.is_some() {
+            return Ok(false);
+        }
+        self.has_worktree_git_dir(context)
+    }
+
+    fn has_worktree_git_dir(&self, context: &GitContext) -> Result<bool, GitError> {
+        let path = match (self.path(), &self.in_index) {
+            (Some(path), Some(_)) => path,
+            _ => return Ok(false),
+        };
+        let top_level_dir = context.top_level_dir()?;
+        Ok(top_level_dir.join(path).join(".git").is_dir())
+    }
+
+    /// Get the data of .git/modules/<name> as it will be after the embedded git directory of the
+    /// submodule is absorbed, with the worktree as an absolute path
+    fn absorbed_git_module(&self, context: &GitContext) -> Result<InGitModule, GitError> {
+        let name = self.name().unwrap_or_default().to_string();
+        let worktree = context
+            .top_level_dir()?
+            .join(self.path().unwrap_or_default());
+        let head_sha = match GitContext::try_from(&worktree) {
+            Ok(sub_context) => sub_context.head()?,
+            Err(_) => None,
+        };
+        let git_dir = context.git_dir()?.join("modules").join(&name);
+        Ok(InGitModule {
+            name,
+            worktree: Some(worktree.to_cmd_arg()),
+            head_sha,
+            git_dir: Some(git_dir.to_cmd_arg()),
+        })
+    }
+
+    /// Move the embedded git directory of the submodule into .git/modules/<name> with
+    /// `git submodule absorbgitdirs`, see [`has_embedded_git_dir`](Self::has_embedded_git_dir)
+    ///
+    /// Returns if the git directory is absorbed
+    pub fn absorb_git_dir(&mut self, context: &GitContext) -> Result<bool, GitError> {
+        if !self.has_embedded_git_dir(context)? {
+            return Ok(false);
+        }
+        let (name, path) = match (self.name(), self.path()) {
+            (Some(name), Some(path)) => (name.to_string(), path),
+            _ => return Ok(false),
+        };
+        Action::AbsorbGitDir(path.to_string()).run(context)?;
+        self.in_modules = crate::status::Status::read_git_module(&name, context).ok();
+        Ok(true)
+    }
+
+    /// Get the uncommitted changes in the worktree of the submodule. Returns [`None`] if the
+    /// submodule is not initialized
+    pub fn worktree_changes(
+        &self,
+        context: &GitContext,
+    ) -> Result<Option<WorktreeChanges>, GitError> {
+        let sub_context = match self.open(context) {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let lines = sub_context.status_porcelain()?;
+        Ok(Some(WorktreeChanges::from_porcelain(&lines)))
+    }
+
+    /// Count the commits the index commit is behind and ahead of the remote-tracking ref of the
+    /// tracked branch
+    ///
+    /// Only refs that are already fetched are used. Returns [`None`] if the submodule doesn't
+    /// have a branch configured, is not initialized, or the commits are not available.
+    pub fn upstream_counts(
+        &self,
+        context: &GitContext,
+    ) -> Result<Option<UpstreamCounts>, GitError> {
+        let index_commit = match self.index_commit() {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let (remote, branch) = match self.upstream(context)? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let sub_context = match self.open(context) {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let upstream = format!("{remote}/{branch}");
+        let tracking_ref = format!("refs/remotes/{upstream}");
+        if sub_context.rev_parse(&tracking_ref).is_none() {
+            println_verbose!("Remote-tracking ref `{tracking_ref}` not found");
+            return Ok(None);
+        }
+        match sub_context.count_left_right(index_commit, &tracking_ref) {
+            Ok((ahead, behind)) => Ok(Some(UpstreamCounts {
+                upstream,
+                ahead,
+                behind,
+            })),
+            Err(e) => {
+                println_verbose!("Failed to count commits against `{tracking_ref}`: {e}");
+                Ok(None)
+            }
+        }
+    }
+
     /// Print status
     pub fn print(
         &self,
         context: &GitContext,
         dir_switch: &str,
         long: bool,
+    ) -> Result<(), GitError> {
+        self.print_indented(context, dir_switch, long, "")
+    }
+
+    /// Print status, with each line prefixed by `indent`
+    pub fn print_indented(
+        &self,
+        context: &GitContext,
+        dir_switch: &str,
+        long: bool,
+        indent: &str,
     ) -> Result<(), GitError> {
         let name = match self.name() {
             Some(name) => format!("\"{name}\""),
             None => "<unknown>".to_string(),
         };
+        let upstream_counts = self.upstream_counts(context)?;
+        let changes = self.worktree_changes(context)?.filter(|x| !x.is_clean());
 
         if long {
-            println_info!("submodule {name}:");
+            println_info!("{indent}submodule {name}:");
             if let Some(url) = self.url() {
-                println_info!("  from {url}");
+                println_info!("{indent}  from {url}");
+            }
+            if let Some(version_req) = self.version_req() {
+                println_info!("{indent}  update version is {version_req}");
             }
             if let Some(branch) = self.branch() {
-                println_info!("  update branch is {branch}");
+                match &upstream_counts {
+                    Some(counts) => {
+                        println_info!(
+                            "{indent}  update branch is {branch} ({})",
+                            counts.describe()
+                        );
+                    }
+                    None => {
+                        println_info!("{indent}  update branch is {branch}");
+                    }
+                }
             }
         } else {
-            print_info!("{name:<15}");
+            print_info!("{indent}{name:<15}");
         }
 
         let path = self.path();
         if let Some(index_commit) = self.index_commit() {
             let index_commit_short = &index_commit[..7];
             if long {
-                print_info!("  {index_commit_short}");
+                print_info!("{indent}  {index_commit_short}");
             } else {
                 print_info!(" at {index_commit_short}");
             }
@@ -155,10 +473,10 @@ impl Submodule {
                 Some(path) => {
                     let describe = {
                         let mut x = None;
-                        if let Ok(top_level_dir) = context.top_level_dir() {
-                            if let Ok(context) = GitContext::try_from(top_level_dir.join(path)) {
-                                x = context.describe(index_commit);
-                            }
+                        if let Ok(top_level_dir) = context.top_level_dir()
+                            && let Ok(context) = GitContext::try_from(top_level_dir.join(path))
+                        {
+                            x = context.describe(index_commit);
                         }
                         x
                     };
@@ -177,49 +495,68 @@ impl Submodule {
             }
         }
         if let Some(head_commit) = self.head_commit() {
-            if let Some(index_commit) = self.index_commit() {
-                if head_commit != index_commit {
-                    let head_commit_short = &head_commit[..7];
-                    let mut describe = String::new();
+            if let Some(index_commit) = self.index_commit()
+                && head_commit != index_commit
+            {
+                let head_commit_short = &head_commit[..7];
+                let mut describe = String::new();
+                if let Some(path) = path
+                    && let Ok(top_level_dir) = context.top_level_dir()
+                    && let Ok(context) = GitContext::try_from(top_level_dir.join(path))
+                    && let Some(x) = context.describe(head_commit)
+                {
+                    describe = format!(" ({x})");
+                }
+                if long {
+                    println_warn!("{indent}! checked out {head_commit_short}{describe}");
                     if let Some(path) = path {
-                        if let Ok(top_level_dir) = context.top_level_dir() {
-                            if let Ok(context) = GitContext::try_from(top_level_dir.join(path)) {
-                                if let Some(x) = context.describe(head_commit) {
-                                    describe = format!(" ({x})");
-                                }
-                            }
-                        }
-                    }
-                    if long {
-                        println_warn!("! checked out {head_commit_short}{describe}");
-                        if let Some(path) = path {
-                            let path = quote_arg(path);
-                            let git_c = match context.get_top_level_switch()? {
-                                Some(x) => format!("git -C {x}"),
-                                None => "git".to_string(),
-                            };
-
-                            println_hint!(
-                                "    run `{git_c} submodule update -- {path}` to revert this submodule to index (`magoo{dir_switch} install` to revert all)"
-                            );
-                            println_hint!(
-                                "    run `{git_c} add {path}` update the index to {head_commit_short}{describe}"
-                            );
-                        } else {
-                            println_hint!(
-                                "    run `magoo{dir_switch} install` to revert all submodules to index"
-                            );
-                        }
+                        let path = quote_arg(path);
+                        let git_c = match context.get_top_level_switch()? {
+                            Some(x) => format!("git -C {x}"),
+                            None => "git".to_string(),
+                        };
+
+                        println_hint!(
+                            "{indent}    run `{git_c} submodule update -- {path}` to revert this submodule to index (`magoo{dir_switch} install` to revert all)"
+                        );
+                        println_hint!(
+                            "{indent}    run `{git_c} add {path}` update the index to {head_commit_short}{describe}"
+                        );
                     } else {
-                        print_warn!(", checked out {head_commit_short}{describe}");
+                        println_hint!(
+                            "{indent}    run `magoo{dir_switch} install` to revert all submodules to index"
+                        );
                     }
+                } else {
+                    print_warn!(", checked out {head_commit_short}{describe}");
                 }
             }
+        } else if self.has_embedded_git_dir(context)? {
+            if long {
+                println_warn!("{indent}! has an embedded git directory");
+                println_hint!(
+                    "{indent}    run `magoo{dir_switch} absorb` to move it into .git/modules"
+                );
+            } else {
+                print_warn!(", embedded git directory");
+            }
+        } else if self.is_unregistered_repo(context)? {
+            if long {
+                println_warn!("{indent}! is a git repository not in .gitmodules");
+                if let Some(path) = path {
+                    println_hint!(
+                        "{indent}    run `magoo{dir_switch} adopt {}` to register it as a submodule",
+                        quote_arg(path)
+                    );
+                }
+            } else {
+                print_warn!(", unregistered git repository");
+            }
         } else {
             // not initialized
             if let Some(path) = path {
                 if long {
-                    println_warn!("! not initialized");
+                    println_warn!("{indent}! not initialized");
                     let path = quote_arg(path);
                     let git_c = match context.get_top_level_switch()? {
                         Some(x) => format!("git -C {x}"),
@@ -227,10 +564,10 @@ impl Submodule {
                     };
 
                     println_hint!(
-                        "    run `magoo{dir_switch} install` to initialize all submodules"
+                        "{indent}    run `magoo{dir_switch} install` to initialize all submodules"
                     );
                     println_hint!(
-                        "    run `{git_c} submodule update --init -- {path}` to initialize only this submodule"
+                        "{indent}    run `{git_c} submodule update --init -- {path}` to initialize only this submodule"
                     );
                 } else {
                     print_warn!(", not initialized");
@@ -238,22 +575,34 @@ impl Submodule {
             }
         }
 
+        if let Some(changes) = &changes {
+            if long {
+                println_warn!("{indent}! has uncommitted changes ({})", changes.describe());
+                if let Some(path) = path {
+                    let path = quote_arg(path);
+                    let git_c = match context.get_top_level_switch()? {
+                        Some(x) => format!("git -C {x} -C {path}"),
+                        None => format!("git -C {path}"),
+                    };
+                    println_hint!("{indent}    run `{git_c} status` to see the changes");
+                }
+            } else {
+                print_warn!(", dirty ({})", changes.describe());
+            }
+        }
+
         if !long {
+            if let Some(counts) = &upstream_counts
+                && !counts.is_up_to_date()
+            {
+                print_warn!(", {}", counts.describe());
+            }
             println_info!();
         }
 
-        if !self.is_module_consistent(context)? {
-            println_error!("! submodule has residue");
-            println_hint!("    run `magoo{dir_switch} status --fix` to fix all submodules");
-        }
-        if !self.resolved_paths(context)?.is_consistent() {
-            println_error!("! inconsistent paths");
-            println_hint!("    run `magoo{dir_switch} status --fix` to fix all submodules");
-        }
-        let issue = self.find_issue();
-        if issue != PartsIssue::None {
-            println_error!("! inconsistent state ({})", issue.describe());
-            println_hint!("    run `magoo{dir_switch} status --fix` to fix all submodules");
+        for issue in self.issues(context)? {
+            println_error!("{indent}! {}", issue.describe());
+            println_hint!("{indent}    run `magoo{dir_switch} status --fix` to fix all submodules");
         }
 
         if long {
@@ -265,16 +614,48 @@ impl Submodule {
 
     /// Return false if the submodule has issues that can be fixed with [`fix`]
     pub fn is_healthy(&self, context: &GitContext) -> Result<bool, GitError> {
+        Ok(self.issues(context)?.is_empty())
+    }
+
+    /// Get the issues of the submodule that can be fixed with [`fix`]. Empty if the submodule
+    /// is healthy
+    pub fn issues(&self, context: &GitContext) -> Result<Vec<Issue>, GitError> {
+        let mut issues = Vec::new();
         if !self.is_module_consistent(context)? {
-            return Ok(false);
+            issues.push(Issue::InconsistentModule);
         }
         if !self.resolved_paths(context)?.is_consistent() {
-            return Ok(false);
+            issues.push(Issue::InconsistentPaths);
         }
-        if self.find_issue() != PartsIssue::None {
-            return Ok(false);
+        if let Some(issue) = self.find_parts_issue() {
+            // a submodule with an embedded git directory doesn't have .git/modules/<name>,
+            // which looks like residue, but it will be absorbed when fixing
+            if issue != Issue::Residue || !self.has_embedded_git_dir(context)? {
+                issues.push(issue);
+            }
         }
-        Ok(true)
+        Ok(issues)
+    }
+
+    /// Check the submodule for problems, including ones that don't make it unhealthy
+    /// (not initialized, or checked out a different commit from the index)
+    pub fn check(&self, context: &GitContext) -> Result<Vec<CheckFailure>, GitError> {
+        let mut failures = self
+            .issues(context)?
+            .into_iter()
+            .map(CheckFailure::Unhealthy)
+            .collect::<Vec<_>>();
+        if let Some(index_commit) = self.index_commit() {
+            match self.head_commit() {
+                None => failures.push(CheckFailure::NotInitialized),
+                Some(head_commit) => {
+                    if head_commit != index_commit {
+                        failures.push(CheckFailure::CheckedOutDiffers);
+                    }
+                }
+            }
+        }
+        Ok(failures)
     }
 
     /// Get if the module data and the submodule's worktree is consistent, see [`InGitModule::is_consistent`]
@@ -290,12 +671,12 @@ impl Submodule {
     /// Resolves the paths stored in various places and return them
     pub fn resolved_paths(&self, context: &GitContext) -> Result<SubmodulePaths, GitError> {
         let mut path_in_gitmodules = None;
-        if let Some(in_gitmodules) = &self.in_gitmodules {
-            if let Some(path) = &in_gitmodules.path {
-                let top_level_dir = context.top_level_dir()?;
-                if let Ok(path) = top_level_dir.join(path).canonicalize() {
-                    path_in_gitmodules = Some(path);
-                }
+        if let Some(in_gitmodules) = &self.in_gitmodules
+            && let Some(path) = &in_gitmodules.path
+        {
+            let top_level_dir = context.top_level_dir()?;
+            if let Ok(path) = top_level_dir.join(path).canonicalize() {
+                path_in_gitmodules = Some(path);
             }
         }
 
@@ -308,17 +689,17 @@ impl Submodule {
         }
 
         let mut path_in_module = None;
-        if let Some(in_module) = &self.in_modules {
-            if let Some(worktree) = &in_module.worktree {
-                let git_dir = context.git_dir()?;
-                if let Ok(path) = git_dir
-                    .join("modules")
-                    .join(&in_module.name)
-                    .join(worktree)
-                    .canonicalize()
-                {
-                    path_in_module = Some(path);
-                }
+        if let Some(in_module) = &self.in_modules
+            && let Some(worktree) = &in_module.worktree
+        {
+            let git_dir = context.git_dir()?;
+            if let Ok(path) = git_dir
+                .join("modules")
+                .join(&in_module.name)
+                .join(worktree)
+                .canonicalize()
+            {
+                path_in_module = Some(path);
             }
         }
 
@@ -336,6 +717,22 @@ impl Submodule {
     /// 2. The submodule is healthy but not initialized.
     /// 3. The submodule is deleted.
     pub fn fix(&mut self, context: &GitContext, prefer_delete: bool) -> Result<(), GitError> {
+        // the rest of the fix is planned on the actual module directory after absorbing
+        self.absorb_git_dir(context)?;
+        let actions = self.plan_fix(context, prefer_delete)?;
+        run_fix_actions(context, &actions)
+    }
+
+    /// Plan the actions to fix the submodule, see [`fix`](Self::fix). The submodule is updated
+    /// to the state after the actions are run.
+    ///
+    /// If the submodule has an embedded git directory, the plan absorbs it into .git/modules
+    /// first, and the rest is planned on the module directory that absorbing would create.
+    pub fn plan_fix(
+        &mut self,
+        context: &GitContext,
+        prefer_delete: bool,
+    ) -> Result<Vec<Action>, GitError> {
         // the submodule can be in any shape or form
         // here are some notations:
         // - `G`: submodule data in .gitmodules is [`Some`]
@@ -343,9 +740,18 @@ impl Submodule {
         // - `M`: submodule data in .git/modules/<name> is [`Some`]
         // - `I`: submodule data in the index is [`Some`]
 
+        let mut actions = Vec::new();
+        // Move the embedded git directory into .git/modules, so the worktree is not deleted.
+        // The module directory created by git is consistent
+        if self.has_embedded_git_dir(context)?
+            && let Some(path) = self.path()
+        {
+            actions.push(Action::AbsorbGitDir(path.to_string()));
+            self.in_modules = Some(self.absorbed_git_module(context)?);
+        }
         // First, we want to be in a state where, if a component exists, it is consistent internally and with others
-        if !self.is_module_consistent(context)? {
-            self.force_remove_module_dir(context)?;
+        else if !self.is_module_consistent(context)? {
+            actions.extend(self.plan_remove_module_dir(context)?);
         }
 
         // make sure all paths are consistent
@@ -360,136 +766,156 @@ impl Submodule {
                 if self.in_modules.is_some() && resolved_paths.in_index != resolved_paths.in_modules
                 {
                     // module has different path, delete it
-                    self.force_remove_module_dir(context)?;
+                    actions.extend(self.plan_remove_module_dir(context)?);
                 }
-                if let Some(in_gitmodules) = &self.in_gitmodules {
-                    if resolved_paths.in_index != resolved_paths.in_gitmodules {
-                        let name = &in_gitmodules.name;
-                        // gitmodules has different path, update it
-                        let top_level_dir = context.top_level_dir()?;
-                        context.set_config(
-                            top_level_dir.join(".gitmodules"),
-                            &format!("submodule.\"{name}\".path"),
-                            Some(&index_path),
-                        )?;
-                    }
+                if let Some(in_gitmodules) = &mut self.in_gitmodules
+                    && resolved_paths.in_index != resolved_paths.in_gitmodules
+                {
+                    let name = &in_gitmodules.name;
+                    // gitmodules has different path, update it
+                    let top_level_dir = context.top_level_dir()?;
+                    actions.push(Action::SetConfig(
+                        top_level_dir.join(".gitmodules"),
+                        format!("submodule.\"{name}\".path"),
+                        index_path.clone(),
+                    ));
+                    in_gitmodules.path = Some(index_path);
                 }
             }
         }
 
-        self.fix_issue(self.find_issue(), context, prefer_delete)?;
-        Ok(())
+        actions.extend(self.plan_fix_issue(self.find_parts_issue(), context, prefer_delete)?);
+        Ok(actions)
     }
 
-    fn fix_issue(
+    fn plan_fix_issue(
         &mut self,
-        issue: PartsIssue,
+        issue: Option<Issue>,
         context: &GitContext,
         prefer_delete: bool,
-    ) -> Result<(), GitError> {
-        match issue {
-            PartsIssue::None => {
+    ) -> Result<Vec<Action>, GitError> {
+        let issue = match issue {
+            Some(issue) => issue,
+            None => {
                 // submodule is healthy
+                return Ok(vec![]);
+            }
+        };
+        let mut actions = Vec::new();
+        match issue {
+            Issue::InconsistentModule | Issue::InconsistentPaths => {
+                // not a parts issue, fixed in `plan_fix` before this
             }
-            PartsIssue::Residue => {
+            Issue::Residue => {
                 // submodule is not initialized but module dir exists
                 println_verbose!("Fix: removing uninitialized submodule directory and worktree");
-                self.force_remove_config(context)?;
-                self.force_remove_module_dir(context)?;
+                actions.extend(self.plan_remove_config(context)?);
+                actions.extend(self.plan_remove_module_dir(context)?);
             }
-            PartsIssue::MissingIndex => {
+            Issue::MissingIndex => {
                 // index is missing
                 // try installing it from the info in submodule
-                if !prefer_delete {
-                    if let Some(gitmodule) = &self.in_gitmodules {
-                        // url and path are required
-                        if let (Some(url), Some(path)) = (&gitmodule.url, &gitmodule.path) {
-                            println_verbose!("Fix: adding submodule from .gitmodules");
-                            let result = context.submodule_add(
-                                url.as_ref(),
-                                Some(path.as_ref()),
-                                gitmodule.branch.as_deref(),
-                                Some(gitmodule.name.as_ref()),
-                                None,
-                                false,
-                            );
-                            if let Err(e) = result {
-                                println_error!("Failed to add submodule as part of --fix: {e}");
-                                println_hint!("To delete it instead, add the `--delete` flag");
-                                return Err(e);
-                            }
-                            return Ok(());
-                        }
+                if !prefer_delete && let Some(gitmodule) = &self.in_gitmodules {
+                    // url and path are required
+                    if let (Some(url), Some(path)) = (&gitmodule.url, &gitmodule.path) {
+                        println_verbose!("Fix: adding submodule from .gitmodules");
+                        actions.push(Action::SubmoduleAdd {
+                            url: url.clone(),
+                            path: Some(path.clone()),
+                            branch: gitmodule.branch.clone(),
+                            name: Some(gitmodule.name.clone()),
+                            depth: None,
+                            force: false,
+                        });
+                        return Ok(actions);
                     }
                 }
                 // if we can't add from .gitmodules, delete it
                 println_verbose!("Fix: deleting submodule missing in index");
-                self.force_delete(context)?;
+                actions.extend(self.plan_delete(context)?);
             }
-            PartsIssue::MissingInGitModules => {
+            Issue::MissingInGitModules => {
                 // submodule is not in .gitmodules
                 // delete it
                 println_verbose!("Fix: deleting submodule missing in .gitmodules");
-                self.force_delete(context)?;
+                actions.extend(self.plan_delete(context)?);
             }
-            PartsIssue::MissingIndexAndGitModules => {
+            Issue::MissingIndexAndGitModules => {
                 // submodule is not in .gitmodules
                 // delete it
                 println_verbose!("Fix: deleting submodule missing in index and .gitmodules");
-                self.force_delete(context)?;
+                actions.extend(self.plan_delete(context)?);
             }
         };
-        Ok(())
+        Ok(actions)
+    }
+
+    /// Get which places the submodule exists in (G/C/M/I, see [`fix`])
+    pub fn parts(&self) -> SubmoduleParts {
+        SubmoduleParts {
+            in_gitmodules: self.in_gitmodules.is_some(),
+            in_config: self.in_config.is_some(),
+            in_modules: self.in_modules.is_some(),
+            in_index: self.in_index.is_some(),
+        }
     }
 
-    fn find_issue(&self) -> PartsIssue {
+    /// Find the issue from the combination of places the submodule exists in (G/C/M/I, see [`fix`])
+    fn find_parts_issue(&self) -> Option<Issue> {
+        let parts = self.parts();
         match (
-            &self.in_gitmodules,
-            &self.in_config,
-            &self.in_modules,
-            &self.in_index,
+            parts.in_gitmodules,
+            parts.in_config,
+            parts.in_modules,
+            parts.in_index,
         ) {
-            (None, None, None, None) => {
+            (false, false, false, false) => {
                 // submodule doesn't exist
-                PartsIssue::None
+                None
             }
-            (Some(_), Some(_), Some(_), Some(_)) => {
+            (true, true, true, true) => {
                 // initialized and all good
-                PartsIssue::None
+                None
             }
-            (Some(_), None, None, Some(_)) => {
+            (true, false, false, true) => {
                 // submodule is in .gitmodules and index (not initialized)
                 // nothing to fix
-                PartsIssue::None
+                None
             }
-            (Some(_), Some(_), None, Some(_)) => {
+            (true, true, false, true) => {
                 // there are remains after submodule is deinitialized
-                PartsIssue::Residue
+                Some(Issue::Residue)
             }
-            (Some(_), None, Some(_), Some(_)) => PartsIssue::Residue,
-            (Some(_), _, _, None) => {
+            (true, false, true, true) => Some(Issue::Residue),
+            (true, _, _, false) => {
                 // index is missing, but is in .gitmodules
-                PartsIssue::MissingIndex
+                Some(Issue::MissingIndex)
             }
-            (None, _, _, None) => {
+            (false, _, _, false) => {
                 // submodule is not in .gitmodules or index
-                PartsIssue::MissingIndexAndGitModules
+                Some(Issue::MissingIndexAndGitModules)
             }
-            (None, _, _, _) => {
+            (false, _, _, true) => {
                 // submodule is not in .gitmodules
-                PartsIssue::MissingInGitModules
+                Some(Issue::MissingInGitModules)
             }
         }
     }
 
     /// Delete the submodule by removing the configuration and directories that reference it
     pub fn force_delete(&mut self, context: &GitContext) -> Result<(), GitError> {
-        self.force_remove_from_index(context)?;
-        self.force_remove_module_dir(context)?;
-        self.force_remove_config(context)?;
-        self.force_remove_from_dot_gitmodules(context)?;
+        let actions = self.plan_delete(context)?;
+        run_actions(context, &actions)
+    }
 
-        Ok(())
+    /// Plan the actions to delete the submodule, see [`force_delete`](Self::force_delete)
+    pub fn plan_delete(&mut self, context: &GitContext) -> Result<Vec<Action>, GitError> {
+        let mut actions = self.plan_remove_from_index(context)?;
+        actions.extend(self.plan_remove_module_dir(context)?);
+        actions.extend(self.plan_remove_config(context)?);
+        actions.extend(self.plan_remove_from_dot_gitmodules(context)?);
+        dedup_adds(&mut actions);
+        Ok(actions)
     }
 
     /// Delete the submodule section in .gitmodules
@@ -497,76 +923,419 @@ impl Submodule {
         &mut self,
         context: &GitContext,
     ) -> Result<(), GitError> {
+        let actions = self.plan_remove_from_dot_gitmodules(context)?;
+        run_actions(context, &actions)
+    }
+
+    /// Plan the actions to delete the submodule section in .gitmodules
+    pub fn plan_remove_from_dot_gitmodules(
+        &mut self,
+        context: &GitContext,
+    ) -> Result<Vec<Action>, GitError> {
+        let mut actions = Vec::new();
         if let Some(in_gitmodules) = &self.in_gitmodules {
             let top_level_dir = context.top_level_dir()?;
             let name = &in_gitmodules.name;
-            println_info!("Deleting submodule `{name}` in .gitmodules");
-            let _ = context.remove_config_section(
+            actions.push(Action::RemoveConfigSection(
                 top_level_dir.join(".gitmodules"),
-                &format!("submodule.{name}"),
-            );
+                format!("submodule.{name}"),
+            ));
             // add may fail if .gitmodules doesn't exist
-            let _ = context.add(".gitmodules");
+            actions.push(Action::Add(".gitmodules".to_string()));
         }
         self.in_gitmodules = None;
-        Ok(())
+        Ok(actions)
     }
 
     /// Remove the submodule from index
     pub fn force_remove_from_index(&mut self, context: &GitContext) -> Result<(), GitError> {
+        let actions = self.plan_remove_from_index(context)?;
+        run_actions(context, &actions)
+    }
+
+    /// Plan the actions to remove the submodule from index
+    pub fn plan_remove_from_index(
+        &mut self,
+        _context: &GitContext,
+    ) -> Result<Vec<Action>, GitError> {
+        let mut actions = Vec::new();
         if let Some(in_index) = &self.in_index {
-            println_info!("Deleting `{}` in index", in_index.path);
-            context.remove_from_index(&in_index.path)?;
+            actions.push(Action::RemoveFromIndex(in_index.path.clone()));
             // add may fail if .gitmodules doesn't exist
-            let _ = context.add(".gitmodules");
+            actions.push(Action::Add(".gitmodules".to_string()));
         }
         self.in_index = None;
+        Ok(actions)
+    }
+
+    /// Turn the submodule into regular files tracked by the superproject at the same path.
+    ///
+    /// The gitlink, the sections in .gitmodules and .git/config, and .git/modules/<name> are
+    /// removed, then the files in the worktree are added to the index. The submodule should be
+    /// initialized.
+    pub fn vendor(&mut self, context: &GitContext) -> Result<(), GitError> {
+        let name = self.name().unwrap_or_default().to_string();
+        let path = match (self.path(), self.head_commit()) {
+            (Some(path), Some(_)) => path.to_string(),
+            _ => return Err(GitError::ModuleNotFound(name)),
+        };
+        let top_level_dir = context.top_level_dir()?;
+        let worktree = top_level_dir.join(&path);
+
+        println_info!("Deleting `{path}` in index");
+        context.remove_cached(&path)?;
+        self.in_index = None;
+        self.force_remove_config(context)?;
+        self.force_remove_from_dot_gitmodules(context)?;
+
+        // the worktree is kept, only the link to the module directory is removed
+        let git_file = worktree.join(".git");
+        println_verbose!("Deleting `{}`", git_file.to_cmd_arg());
+        std::fs::remove_file(&git_file)
+            .map_err(|e| GitError::WriteFailed(git_file.to_cmd_arg(), e))?;
+        if let Some(in_module) = &mut self.in_modules {
+            in_module.worktree = None;
+        }
+        self.force_remove_module_dir(context)?;
+
+        println_info!("Adding the files in `{path}` to index");
+        context.add(&path)?;
+        Ok(())
+    }
+
+    /// Move the worktree of the submodule to a new path from the top level, and update the path
+    /// in .gitmodules, the index and `core.worktree` of .git/modules/<name>
+    ///
+    /// The new path must be inside the repository, see [`normalize_repo_path`]. The submodule
+    /// should be healthy before moving. The changes are reverted if any step fails.
+    pub fn move_to(&self, context: &GitContext, new_path: &str) -> Result<(), GitError> {
+        let name = self.name().unwrap_or_default();
+        let (old_path, commit) = match (self.path(), self.index_commit()) {
+            (Some(path), Some(commit)) => (path, commit),
+            _ => return Err(GitError::ModuleNotFound(name.to_string())),
+        };
+        let new_path = normalize_repo_path(new_path)
+            .ok_or_else(|| GitError::PathOutsideRepo(new_path.to_string()))?;
+        let top_level_dir = context.top_level_dir()?;
+        let old_worktree = top_level_dir.join(old_path);
+        let new_worktree = top_level_dir.join(&new_path);
+
+        // the config and index are updated first, since they are easy to revert
+        if let Err(e) = self.set_path(context, old_path, &new_path, commit) {
+            self.revert_path(context, old_path, &new_path, commit);
+            return Err(e);
+        }
+        if let Err(e) = self.move_worktree(context, &old_worktree, &new_worktree) {
+            if new_worktree.exists() && !old_worktree.exists() {
+                let _ = self.move_worktree(context, &new_worktree, &old_worktree);
+            }
+            self.revert_path(context, old_path, &new_path, commit);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Update the path of the submodule in .gitmodules and the index
+    fn set_path(
+        &self,
+        context: &GitContext,
+        old_path: &str,
+        new_path: &str,
+        commit: &str,
+    ) -> Result<(), GitError> {
+        let name = self.name().unwrap_or_default();
+        context.set_config(
+            context.top_level_dir()?.join(".gitmodules"),
+            &format!("submodule.{name}.path"),
+            Some(new_path),
+        )?;
+        context.add(".gitmodules")?;
+        context.remove_cached(old_path)?;
+        context.add_gitlink(new_path, commit)?;
+        Ok(())
+    }
+
+    /// Put the old path of the submodule back in .gitmodules and the index after
+    /// [`set_path`](Self::set_path) fails or the worktree cannot be moved. Errors are ignored,
+    /// since some steps might not have run
+    fn revert_path(&self, context: &GitContext, old_path: &str, new_path: &str, commit: &str) {
+        println_verbose!("Reverting the path of submodule to `{old_path}`");
+        let name = self.name().unwrap_or_default();
+        if let Ok(top_level_dir) = context.top_level_dir() {
+            let _ = context.set_config(
+                top_level_dir.join(".gitmodules"),
+                &format!("submodule.{name}.path"),
+                Some(old_path),
+            );
+        }
+        let _ = context.add(".gitmodules");
+        let _ = context.remove_cached(new_path);
+        let _ = context.add_gitlink(old_path, commit);
+    }
+
+    /// Move the worktree and connect it with .git/modules/<name>
+    fn move_worktree(&self, context: &GitContext, from: &Path, to: &Path) -> Result<(), GitError> {
+        if from.exists() {
+            if let Some(parent) = to.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| GitError::WriteFailed(parent.to_cmd_arg(), e))?;
+            }
+            println_verbose!("Moving `{}` to `{}`", from.to_cmd_arg(), to.to_cmd_arg());
+            std::fs::rename(from, to)
+                .map_err(|e| GitError::RenameFailed(from.to_cmd_arg(), to.to_cmd_arg(), e))?;
+            remove_empty_parents(from, context.top_level_dir()?);
+        }
+
+        if self.in_modules.is_some() {
+            let name = self.name().unwrap_or_default();
+            let module_dir = context.git_dir()?.join("modules").join(name);
+            connect_worktree(context, &module_dir, to)?;
+            if let Ok(sub_context) = GitContext::try_from(to) {
+                connect_nested_worktrees(&sub_context)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rename the submodule in .gitmodules, .git/config and .git/modules, and point the worktree
+    /// to the new module directory
+    ///
+    /// The submodule should be healthy before renaming.
+    pub fn rename(&self, context: &GitContext, new_name: &str) -> Result<(), GitError> {
+        let name = match self.name() {
+            Some(name) => name,
+            None => return Err(GitError::ModuleNotFound(String::new())),
+        };
+        let top_level_dir = context.top_level_dir()?;
+        let git_dir = context.git_dir()?;
+
+        let old_module_dir = git_dir.join("modules").join(name);
+        let new_module_dir = git_dir.join("modules").join(new_name);
+        if self.in_modules.is_some() && old_module_dir.exists() {
+            if new_module_dir.exists() || new_module_dir.starts_with(&old_module_dir) {
+                return Err(GitError::RenameFailed(
+                    format!(".git/modules/{name}"),
+                    format!(".git/modules/{new_name}"),
+                    std::io::ErrorKind::AlreadyExists.into(),
+                ));
+            }
+            if let Some(parent) = new_module_dir.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| GitError::WriteFailed(parent.to_cmd_arg(), e))?;
+            }
+            println_verbose!("Moving `.git/modules/{name}` to `.git/modules/{new_name}`");
+            std::fs::rename(&old_module_dir, &new_module_dir).map_err(|e| {
+                GitError::RenameFailed(
+                    format!(".git/modules/{name}"),
+                    format!(".git/modules/{new_name}"),
+                    e,
+                )
+            })?;
+            remove_empty_parents(&old_module_dir, &git_dir.join("modules"));
+        }
+
+        let old_section = format!("submodule.{name}");
+        let new_section = format!("submodule.{new_name}");
+        if self.in_gitmodules.is_some() {
+            context.rename_config_section(
+                top_level_dir.join(".gitmodules"),
+                &old_section,
+                &new_section,
+            )?;
+            context.add(".gitmodules")?;
+        }
+        if self.in_config.is_some() {
+            context.rename_config_section(git_dir.join("config"), &old_section, &new_section)?;
+        }
+
+        if self.in_modules.is_some()
+            && let Some(path) = self.path()
+        {
+            let worktree = top_level_dir.join(path);
+            if worktree.exists() && new_module_dir.exists() {
+                connect_worktree(context, &new_module_dir, &worktree)?;
+                if let Ok(sub_context) = GitContext::try_from(worktree) {
+                    connect_nested_worktrees(&sub_context)?;
+                }
+            }
+        }
+
         Ok(())
     }
 
     /// Delete the submodule in .git/modules/<name> and its worktree if present
     pub fn force_remove_module_dir(&mut self, context: &GitContext) -> Result<(), GitError> {
+        let actions = self.plan_remove_module_dir(context)?;
+        run_actions(context, &actions)
+    }
+
+    /// Plan the actions to delete the submodule in .git/modules/<name> and its worktree if
+    /// present
+    pub fn plan_remove_module_dir(
+        &mut self,
+        context: &GitContext,
+    ) -> Result<Vec<Action>, GitError> {
+        let mut actions = Vec::new();
+        // the module directory may not exist yet if it's planned to be absorbed
         if let Some(in_module) = &self.in_modules {
             let name = &in_module.name;
             let git_dir = context.git_dir()?;
             let module_dir = git_dir.join("modules").join(name);
-            if module_dir.exists() {
-                // delete worktree directory if exists
-                if let Some(worktree) = &in_module.worktree {
-                    let worktree_path = module_dir.join(worktree);
-                    if worktree_path.exists() {
-                        println_info!(
-                            "Deleting the worktree of submodule `{name}` at `{}`",
-                            worktree_path.to_cmd_arg()
-                        );
-                        let _ = std::fs::remove_dir_all(worktree_path);
-                    }
+            // delete worktree directory if exists
+            if let Some(worktree) = &in_module.worktree {
+                let worktree_path = module_dir.join(worktree);
+                if worktree_path.exists() {
+                    actions.push(Action::RemoveDir(worktree_path.canonicalize_git()?));
                 }
-                // delete the module directory
-                println_info!("Deleting `.git/modules/{name}`");
-                let _ = std::fs::remove_dir_all(module_dir);
             }
+            // delete the module directory
+            actions.push(Action::RemoveDir(module_dir));
         }
         self.in_modules = None;
-        Ok(())
+        Ok(actions)
     }
 
     /// Delete the submodule in .git/config
     pub fn force_remove_config(&mut self, context: &GitContext) -> Result<(), GitError> {
+        let actions = self.plan_remove_config(context)?;
+        run_actions(context, &actions)
+    }
+
+    /// Plan the actions to delete the submodule in .git/config
+    pub fn plan_remove_config(&mut self, context: &GitContext) -> Result<Vec<Action>, GitError> {
+        let mut actions = Vec::new();
         if let Some(in_config) = &self.in_config {
             let git_dir = context.git_dir()?;
             let name = &in_config.name;
-            println_info!("Deleting submodule `{name}` in .git/config");
-            let _ =
-                context.remove_config_section(git_dir.join("config"), &format!("submodule.{name}"));
+            actions.push(Action::RemoveConfigSection(
+                git_dir.join("config"),
+                format!("submodule.{name}"),
+            ));
         }
         self.in_config = None;
-        Ok(())
+        Ok(actions)
+    }
+}
+
+/// Run the actions planned by [`Submodule::plan_fix`], with a hint if adding the submodule
+/// back from .gitmodules fails
+fn run_fix_actions(context: &GitContext, actions: &[Action]) -> Result<(), GitError> {
+    for action in actions {
+        if let Err(e) = action.run(context) {
+            if let Action::SubmoduleAdd { .. } = action {
+                println_error!("Failed to add submodule as part of --fix: {e}");
+                println_hint!("To delete it instead, add the `--delete` flag");
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Point `core.worktree` of the module directory to the worktree, and the `.git` file in the
+/// worktree to the module directory, using relative paths like git does
+fn connect_worktree(
+    context: &GitContext,
+    module_dir: &Path,
+    worktree: &Path,
+) -> Result<(), GitError> {
+    let module_dir = module_dir.canonicalize_git()?;
+    let worktree = worktree.canonicalize_git()?;
+    if let Some(path) = pathdiff::diff_paths(&worktree, &module_dir) {
+        let path = path.to_cmd_arg().replace('\\', "/");
+        context.set_config(module_dir.join("config"), "core.worktree", Some(&path))?;
+    }
+    if let Some(path) = pathdiff::diff_paths(&module_dir, &worktree) {
+        let git_file = worktree.join(".git");
+        let content = format!("gitdir: {}\n", path.to_cmd_arg().replace('\\', "/"));
+        std::fs::write(&git_file, content)
+            .map_err(|e| GitError::WriteFailed(git_file.to_cmd_arg(), e))?;
+    }
+    Ok(())
+}
+
+/// Reconnect the worktrees of the initialized submodules nested in the repository, after the
+/// repository is moved
+fn connect_nested_worktrees(context: &GitContext) -> Result<(), GitError> {
+    let status = crate::status::Status::read_from(context)?;
+    let top_level_dir = context.top_level_dir()?;
+    let modules_dir = context.git_dir()?.join("modules");
+    for submodule in status.flattened() {
+        if let (Some(name), Some(path), Some(_)) =
+            (submodule.name(), submodule.path(), &submodule.in_modules)
+        {
+            let worktree = top_level_dir.join(path);
+            if !worktree.exists() {
+                continue;
+            }
+            connect_worktree(context, &modules_dir.join(name), &worktree)?;
+            if let Ok(sub_context) = GitContext::try_from(worktree) {
+                connect_nested_worktrees(&sub_context)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check if `rev` is a full commit hash or a tag, which can be resolved locally without
+/// being stale
+fn is_fixed_rev(sub_context: &GitContext, rev: &str) -> bool {
+    if matches!(rev.len(), 40 | 64) && rev.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return true;
+    }
+    if rev.starts_with("refs/tags/") {
+        return true;
+    }
+    sub_context.rev_parse(&format!("refs/tags/{rev}")).is_some()
+}
+
+/// Fetch `rev` from the default remote of the submodule and resolve it from `FETCH_HEAD`
+fn fetch_rev(sub_context: &GitContext, name: &str, rev: &str) -> Result<String, GitError> {
+    let remote = sub_context.default_remote()?;
+    println_verbose!("Fetching `{rev}` from `{remote}` for submodule `{name}`");
+    sub_context.fetch(&remote, Some(rev))?;
+    sub_context
+        .rev_parse("FETCH_HEAD")
+        .ok_or_else(|| GitError::RevisionNotFound(rev.to_string()))
+}
+
+/// Normalize a path relative from the top level of the repository, resolving `.` and `..` and
+/// using `/` as the separator
+///
+/// Returns [`None`] if the path is absolute, or points to the top level or outside of it.
+pub fn normalize_repo_path(path: &str) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    Some(parts.join("/"))
+}
+
+/// Remove the empty directories from the parent of `path` up to (excluding) `top_level_dir`
+pub(crate) fn remove_empty_parents(path: &Path, top_level_dir: &Path) {
+    let mut dir = path.parent();
+    while let Some(parent) = dir {
+        if parent == top_level_dir || std::fs::remove_dir(parent).is_err() {
+            break;
+        }
+        dir = parent.parent();
     }
 }
 
 /// Data of a submodule stored in .gitmodules
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct InGitmodules {
     /// Name of the submodule, stored in the section name
     pub name: String,
@@ -576,6 +1345,11 @@ pub struct InGitmodules {
     pub url: Option<String>,
     /// Branch of the submodule to update, stored as `submodule.<name>.branch`
     pub branch: Option<String>,
+    /// If the submodule should be cloned shallowly, stored as `submodule.<name>.shallow`
+    pub shallow: Option<bool>,
+    /// Semver requirement of the release tag to update the submodule to, stored as
+    /// `submodule.<name>.magoo-version`
+    pub version: Option<String>,
 }
 
 impl InGitmodules {
@@ -590,6 +1364,7 @@ impl InGitmodules {
 
 /// Data of a submodule stored in the index
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct IndexObject {
     /// Path of the index object
     pub path: String,
@@ -599,6 +1374,7 @@ pub struct IndexObject {
 
 /// Data of a submodule stored in .git/config
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct InGitConfig {
     /// Name of the submodule, stored in the section name
     pub name: String,
@@ -608,6 +1384,7 @@ pub struct InGitConfig {
 
 /// Data of submodule stored in .git/modules
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct InGitModule {
     /// Name of the submodule, which is the part in the path after `.git/modules/`
     pub name: String,
@@ -646,6 +1423,112 @@ impl InGitModule {
     }
 }
 
+/// Number of commits between the index commit of a submodule and the remote-tracking ref of
+/// the branch it tracks
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UpstreamCounts {
+    /// The remote-tracking ref, like `origin/main`
+    pub upstream: String,
+    /// Number of commits in the index commit that are not in the upstream
+    pub ahead: usize,
+    /// Number of commits in the upstream that are not in the index commit
+    pub behind: usize,
+}
+
+impl UpstreamCounts {
+    /// Return true if the index commit is the same as the upstream
+    pub fn is_up_to_date(&self) -> bool {
+        self.ahead == 0 && self.behind == 0
+    }
+
+    /// Get a human-readable description, like `2 behind origin/main`
+    pub fn describe(&self) -> String {
+        let upstream = &self.upstream;
+        match (self.ahead, self.behind) {
+            (0, 0) => format!("up to date with {upstream}"),
+            (0, behind) => format!("{behind} behind {upstream}"),
+            (ahead, 0) => format!("{ahead} ahead of {upstream}"),
+            (ahead, behind) => format!("{ahead} ahead, {behind} behind {upstream}"),
+        }
+    }
+}
+
+/// Uncommitted changes in the worktree of a submodule
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct WorktreeChanges {
+    /// Number of files with changes staged in the index of the submodule
+    pub staged: usize,
+    /// Number of tracked files with changes not staged
+    pub modified: usize,
+    /// Number of untracked files
+    pub untracked: usize,
+}
+
+impl WorktreeChanges {
+    /// Count the changes from the output of `git status --porcelain`
+    pub fn from_porcelain<S>(lines: &[S]) -> Self
+    where
+        S: AsRef<str>,
+    {
+        let mut changes = Self::default();
+        for line in lines {
+            let mut status = line.as_ref().chars();
+            match (status.next(), status.next()) {
+                (Some('?'), Some('?')) => changes.untracked += 1,
+                (Some(x), Some(y)) => {
+                    if x != ' ' {
+                        changes.staged += 1;
+                    }
+                    if y != ' ' {
+                        changes.modified += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        changes
+    }
+
+    /// Return true if there are no changes
+    pub fn is_clean(&self) -> bool {
+        self.staged == 0 && self.modified == 0 && self.untracked == 0
+    }
+
+    /// Get a human-readable description, like `1 staged, 2 untracked`
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.staged > 0 {
+            parts.push(format!("{} staged", self.staged));
+        }
+        if self.modified > 0 {
+            parts.push(format!("{} modified", self.modified));
+        }
+        if self.untracked > 0 {
+            parts.push(format!("{} untracked", self.untracked));
+        }
+        if parts.is_empty() {
+            return "clean".to_string();
+        }
+        parts.join(", ")
+    }
+}
+
+/// Places a submodule exists in, see [`Submodule::parts`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SubmoduleParts {
+    /// The submodule is in .gitmodules (G)
+    pub in_gitmodules: bool,
+    /// The submodule is in .git/config (C)
+    pub in_config: bool,
+    /// The submodule is in .git/modules/<name> (M)
+    pub in_modules: bool,
+    /// The submodule is in the index (I)
+    pub in_index: bool,
+}
+
 /// An issue in the paths in different places
 pub struct SubmodulePaths {
     pub in_gitmodules: Option<PathBuf>,
@@ -665,22 +1548,91 @@ impl SubmodulePaths {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-enum PartsIssue {
-    None,
-    Residue,
-    MissingIndex,
-    MissingInGitModules,
-    MissingIndexAndGitModules,
-}
-impl PartsIssue {
-    pub fn describe(&self) -> &'static str {
-        match self {
-            PartsIssue::None => "none",
-            PartsIssue::Residue => "has residue from removal",
-            PartsIssue::MissingIndex => "missing in index",
-            PartsIssue::MissingInGitModules => "not in .gitmodules",
-            PartsIssue::MissingIndexAndGitModules => "missing in index and .gitmodules",
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submodule(g: bool, c: bool, m: bool, i: bool) -> Submodule {
+        Submodule {
+            in_gitmodules: g.then(|| InGitmodules::with_name("foo")),
+            in_config: c.then(InGitConfig::default),
+            in_modules: m.then(InGitModule::default),
+            in_index: i.then(IndexObject::default),
         }
     }
+
+    #[test]
+    fn test_normalize_repo_path() {
+        assert_eq!(normalize_repo_path("libs/a/").as_deref(), Some("libs/a"));
+        assert_eq!(normalize_repo_path("./libs/../b").as_deref(), Some("b"));
+        assert_eq!(normalize_repo_path("libs/.."), None);
+        assert_eq!(normalize_repo_path("../outside"), None);
+        assert_eq!(normalize_repo_path("libs/../../outside"), None);
+        assert_eq!(normalize_repo_path("/tmp/a"), None);
+    }
+
+    #[test]
+    fn test_parts() {
+        assert_eq!(
+            submodule(true, false, true, false).parts(),
+            SubmoduleParts {
+                in_gitmodules: true,
+                in_config: false,
+                in_modules: true,
+                in_index: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_find_parts_issue() {
+        assert_eq!(
+            submodule(false, false, false, false).find_parts_issue(),
+            None
+        );
+        assert_eq!(submodule(true, true, true, true).find_parts_issue(), None);
+        assert_eq!(submodule(true, false, false, true).find_parts_issue(), None);
+        assert_eq!(
+            submodule(true, true, false, true).find_parts_issue(),
+            Some(Issue::Residue)
+        );
+        assert_eq!(
+            submodule(true, false, true, true).find_parts_issue(),
+            Some(Issue::Residue)
+        );
+        assert_eq!(
+            submodule(true, true, true, false).find_parts_issue(),
+            Some(Issue::MissingIndex)
+        );
+        assert_eq!(
+            submodule(false, true, false, false).find_parts_issue(),
+            Some(Issue::MissingIndexAndGitModules)
+        );
+        assert_eq!(
+            submodule(false, false, false, true).find_parts_issue(),
+            Some(Issue::MissingInGitModules)
+        );
+    }
+
+    #[test]
+    fn test_worktree_changes_from_porcelain() {
+        let changes = WorktreeChanges::from_porcelain(&[
+            "M  staged.txt",
+            " M modified.txt",
+            "MM both.txt",
+            "?? untracked.txt",
+            "A  added.txt",
+        ]);
+        assert_eq!(
+            changes,
+            WorktreeChanges {
+                staged: 3,
+                modified: 2,
+                untracked: 1,
+            }
+        );
+        assert_eq!(changes.describe(), "3 staged, 2 modified, 1 untracked");
+        let empty: [&str; 0] = [];
+        assert!(WorktreeChanges::from_porcelain(&empty).is_clean());
+    }
 }
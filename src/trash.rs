@@ -0,0 +1,416 @@
+//! Recoverable trash for the directories removed by magoo
+//!
+//! Worktrees and `.git/modules/<name>` directories are moved into `.git/magoo-trash/<id>`
+//! instead of being deleted, so unpushed commits in a submodule are not lost. All the directories
+//! removed by one magoo process are put in the same entry.
+//!
+//! `magoo remove` without `--force` runs `git submodule deinit` first, which empties the
+//! worktree, so only `.git/modules/<name>` has anything to recover in that case.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::git::{GitCmdPath, GitContext, GitError};
+use crate::print::println_verbose;
+
+/// Name of the trash directory in the .git directory
+pub const TRASH_DIR: &str = "magoo-trash";
+
+/// Name of the metadata file in each entry
+///
+/// Each line is `key=value`, with backslashes and newlines in the value escaped:
+/// ```text
+/// created=1706745599
+/// command=magoo remove foo
+/// item=0:/path/to/repo/foo
+/// ```
+const METADATA_FILE: &str = "entry.txt";
+
+/// Directories removed by one magoo process
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrashEntry {
+    /// ID of the entry, which is the name of its directory in the trash
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub id: String,
+    /// Unix timestamp (in seconds) of when the entry was created
+    pub created: u64,
+    /// The command line of the magoo process that removed the directories
+    pub command: String,
+    /// The directories in the entry, in the order they are removed
+    pub items: Vec<TrashItem>,
+}
+
+impl TrashEntry {
+    /// Serialize the entry into the content of the metadata file
+    fn to_metadata(&self) -> String {
+        let mut out = format!(
+            "created={}\ncommand={}\n",
+            self.created,
+            escape(&self.command)
+        );
+        for item in &self.items {
+            out.push_str(&format!("item={}:{}\n", item.name, escape(&item.path)));
+        }
+        out
+    }
+
+    /// Parse the content of the metadata file. Returns [`None`] if it's invalid
+    fn parse_metadata(id: &str, content: &str) -> Option<Self> {
+        let mut entry = Self {
+            id: id.to_string(),
+            ..Default::default()
+        };
+        for line in content.lines() {
+            match line.split_once('=')? {
+                ("created", value) => entry.created = value.parse().ok()?,
+                ("command", value) => entry.command = unescape(value),
+                ("item", value) => {
+                    let (name, path) = value.split_once(':')?;
+                    entry.items.push(TrashItem {
+                        name: name.to_string(),
+                        path: unescape(path),
+                    });
+                }
+                _ => return None,
+            }
+        }
+        Some(entry)
+    }
+}
+
+/// A directory in a [`TrashEntry`]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrashItem {
+    /// Name of the directory in the entry
+    pub name: String,
+    /// Absolute path the directory is removed from
+    pub path: String,
+}
+
+/// The trash in `.git/magoo-trash` of a repository
+pub struct Trash {
+    dir: PathBuf,
+}
+
+impl Trash {
+    /// Open the trash of the repository. The directory is created when something is moved into
+    /// it
+    pub fn open(context: &GitContext) -> Result<Self, GitError> {
+        let dir = context.git_dir()?.join(TRASH_DIR);
+        Ok(Self { dir })
+    }
+
+    /// Get the path of the trash directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Move the directory into the entry of the current process
+    ///
+    /// The metadata of the entry is written before moving, so the directory can always be found
+    /// in the trash. It is rolled back if the directory cannot be moved
+    pub fn move_dir(&self, path: &Path) -> Result<(), GitError> {
+        let id = current_entry_id();
+        let entry_dir = self.dir.join(id);
+        let mut entry = self.read_entry(id).unwrap_or_else(|_| TrashEntry {
+            id: id.to_string(),
+            created: now(),
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+            items: vec![],
+        });
+        std::fs::create_dir_all(&entry_dir)
+            .map_err(|e| GitError::WriteFailed(entry_dir.to_cmd_arg(), e))?;
+
+        let name = entry.items.len().to_string();
+        let target = entry_dir.join(&name);
+        entry.items.push(TrashItem {
+            name,
+            path: path.to_cmd_arg(),
+        });
+        self.write_entry(&entry)?;
+        println_verbose!(
+            "Moving `{}` to `{}`",
+            path.to_cmd_arg(),
+            target.to_cmd_arg()
+        );
+        if let Err(e) = move_dir(path, &target) {
+            // the directory is only kept in place if it cannot be copied
+            if !matches!(e, GitError::RenameFailed(..)) {
+                return Err(e);
+            }
+            entry.items.pop();
+            if entry.items.is_empty() {
+                let _ = std::fs::remove_dir_all(&entry_dir);
+            } else {
+                let _ = self.write_entry(&entry);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// List the entries in the trash, oldest first
+    ///
+    /// Directories without valid metadata, for example from a magoo process that was killed,
+    /// are skipped
+    pub fn list(&self) -> Result<Vec<TrashEntry>, GitError> {
+        let mut entries = Vec::new();
+        for id in self.entry_ids() {
+            match self.read_entry(&id) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => println_verbose!("Skipping trash entry `{id}`: {e}"),
+            }
+        }
+        entries.sort_by(|a, b| (a.created, &a.id).cmp(&(b.created, &b.id)));
+        Ok(entries)
+    }
+
+    /// Move the directories in the entry back to where they were removed from, and delete the
+    /// entry
+    ///
+    /// Nothing is moved if any of the original paths is taken by something other than an empty
+    /// directory.
+    pub fn restore(&self, id: &str) -> Result<TrashEntry, GitError> {
+        let entry = self.read_entry(id)?;
+        let entry_dir = self.dir.join(id);
+        for item in &entry.items {
+            let path = Path::new(&item.path);
+            if path.exists() && !is_empty_dir(path) {
+                return Err(GitError::RenameFailed(
+                    entry_dir.join(&item.name).to_cmd_arg(),
+                    item.path.clone(),
+                    std::io::ErrorKind::AlreadyExists.into(),
+                ));
+            }
+        }
+        // restore the parents first, since worktrees can be nested in other worktrees
+        let mut items = entry.items.iter().collect::<Vec<_>>();
+        items.sort_by_key(|x| x.path.len());
+        for item in items {
+            let path = Path::new(&item.path);
+            if path.exists() {
+                let _ = std::fs::remove_dir(path);
+            }
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| GitError::WriteFailed(parent.to_cmd_arg(), e))?;
+            }
+            move_dir(&entry_dir.join(&item.name), path)?;
+        }
+        std::fs::remove_dir_all(&entry_dir)
+            .map_err(|e| GitError::WriteFailed(entry_dir.to_cmd_arg(), e))?;
+        Ok(entry)
+    }
+
+    /// Permanently delete everything in the trash and return the number of entries deleted
+    pub fn empty(&self) -> Result<usize, GitError> {
+        let count = self.entry_ids().len();
+        if self.dir.exists() {
+            std::fs::remove_dir_all(&self.dir)
+                .map_err(|e| GitError::WriteFailed(self.dir.to_cmd_arg(), e))?;
+        }
+        Ok(count)
+    }
+
+    /// Get the names of the directories in the trash
+    fn entry_ids(&self) -> Vec<String> {
+        let read_dir = match std::fs::read_dir(&self.dir) {
+            Ok(x) => x,
+            Err(_) => return vec![],
+        };
+        read_dir
+            .flatten()
+            .filter(|x| x.path().is_dir())
+            .map(|x| x.file_name().to_string_lossy().to_string())
+            .collect()
+    }
+
+    fn read_entry(&self, id: &str) -> Result<TrashEntry, GitError> {
+        let path = self.dir.join(id).join(METADATA_FILE);
+        if !path.exists() {
+            return Err(GitError::TrashNotFound(id.to_string()));
+        }
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| GitError::ReadFailed(path.to_cmd_arg(), e))?;
+        TrashEntry::parse_metadata(id, &content)
+            .ok_or_else(|| GitError::InvalidTrashEntry(path.to_cmd_arg()))
+    }
+
+    fn write_entry(&self, entry: &TrashEntry) -> Result<(), GitError> {
+        let path = self.dir.join(&entry.id).join(METADATA_FILE);
+        std::fs::write(&path, entry.to_metadata())
+            .map_err(|e| GitError::WriteFailed(path.to_cmd_arg(), e))
+    }
+}
+
+/// Get the ID of the entry for the current process, like `20240131-235959-1234`, which is the
+/// UTC time of the first removal and the process ID
+fn current_entry_id() -> &'static str {
+    static ID: OnceLock<String> = OnceLock::new();
+    ID.get_or_init(|| format!("{}-{}", format_timestamp(now()), std::process::id()))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default()
+}
+
+/// Format a unix timestamp as `YYYYMMDD-HHMMSS` in UTC
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+    // convert days since epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                out.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                out.push('\\');
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn is_empty_dir(path: &Path) -> bool {
+    std::fs::read_dir(path).is_ok_and(|mut x| x.next().is_none())
+}
+
+/// Move the directory, or copy it and delete the original if it can't be renamed (for example
+/// across file systems)
+fn move_dir(from: &Path, to: &Path) -> Result<(), GitError> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    println_verbose!("Cannot rename `{}`, copying it instead", from.to_cmd_arg());
+    if let Err(e) = copy_dir(from, to) {
+        let _ = std::fs::remove_dir_all(to);
+        return Err(GitError::RenameFailed(
+            from.to_cmd_arg(),
+            to.to_cmd_arg(),
+            e,
+        ));
+    }
+    std::fs::remove_dir_all(from).map_err(|e| GitError::WriteFailed(from.to_cmd_arg(), e))
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target = to.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(std::fs::read_link(from)?, to)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::copy(from, to).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "19700101-000000");
+        assert_eq!(format_timestamp(951782400), "20000229-000000");
+        assert_eq!(format_timestamp(1792175456), "20261016-183056");
+    }
+
+    #[test]
+    fn test_metadata() {
+        let entry = TrashEntry {
+            id: "20240131-235959-1234".to_string(),
+            created: 1706745599,
+            command: "magoo remove 'a\\b'\nc".to_string(),
+            items: vec![TrashItem {
+                name: "0".to_string(),
+                path: "/repo/a=b:c".to_string(),
+            }],
+        };
+        let content = entry.to_metadata();
+        assert_eq!(
+            content,
+            "created=1706745599\ncommand=magoo remove 'a\\\\b'\\nc\nitem=0:/repo/a=b:c\n"
+        );
+        assert_eq!(TrashEntry::parse_metadata(&entry.id, &content), Some(entry));
+        assert_eq!(TrashEntry::parse_metadata("x", "created=now\n"), None);
+    }
+
+    #[test]
+    fn test_move_dir_rollback() {
+        let base = std::env::temp_dir().join(format!("magoo-test-trash-{}", std::process::id()));
+        let trash = Trash {
+            dir: base.join("trash"),
+        };
+        std::fs::create_dir_all(trash.dir.join("broken")).unwrap();
+        assert!(trash.move_dir(&base.join("missing")).is_err());
+        assert!(!trash.dir.join(current_entry_id()).exists());
+        assert_eq!(trash.list().unwrap(), vec![]);
+        assert_eq!(trash.empty().unwrap(), 1);
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_copy_dir() {
+        let base = std::env::temp_dir().join(format!("magoo-test-copy-{}", std::process::id()));
+        let from = base.join("from");
+        let to = base.join("to");
+        std::fs::create_dir_all(from.join("nested")).unwrap();
+        std::fs::write(from.join("nested").join("file"), "hello").unwrap();
+        copy_dir(&from, &to).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(to.join("nested").join("file")).unwrap(),
+            "hello"
+        );
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -24,6 +24,28 @@ pub fn is_supported(version: &Version) -> bool {
         .any(|v| VersionReq::parse(v).unwrap().matches(version))
 }
 
+/// Parse a release tag like `v1.2.3` or `1.2.3` to a semver
+pub fn parse_tag_version(tag: &str) -> Option<Version> {
+    let version = tag.strip_prefix(['v', 'V']).unwrap_or(tag);
+    Version::parse(version).ok()
+}
+
+/// Find the tag with the highest version that satisfies the requirement. Tags that are not
+/// semver are ignored
+pub fn find_highest_tag<'a, S>(tags: &'a [S], req: &VersionReq) -> Option<&'a str>
+where
+    S: AsRef<str>,
+{
+    tags.iter()
+        .filter_map(|tag| {
+            let tag = tag.as_ref();
+            let version = parse_tag_version(tag)?;
+            req.matches(&version).then_some((version, tag))
+        })
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, tag)| tag)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -70,4 +92,25 @@ mod tests {
             &parse_git_version("git version 2.43.3").unwrap()
         ));
     }
+
+    #[test]
+    fn test_find_highest_tag() {
+        let tags = [
+            "v1.3.0",
+            "v1.4.0",
+            "v1.4.2",
+            "1.10.0",
+            "v2.0.0",
+            "v1.11.0-beta.1",
+            "nightly",
+        ];
+        let req = |x| VersionReq::parse(x).unwrap();
+        assert_eq!(find_highest_tag(&tags, &req("^1.4")), Some("1.10.0"));
+        assert_eq!(find_highest_tag(&tags, &req("~1.4")), Some("v1.4.2"));
+        assert_eq!(find_highest_tag(&tags, &req(">=1")), Some("v2.0.0"));
+        assert_eq!(find_highest_tag(&tags, &req("=1.3.0")), Some("v1.3.0"));
+        assert_eq!(find_highest_tag(&tags, &req("^3")), None);
+        assert_eq!(parse_tag_version("V1.0.0"), Some(Version::new(1, 0, 0)));
+        assert_eq!(parse_tag_version("release-1.0.0"), None);
+    }
 }